@@ -1,15 +1,98 @@
-use std::{collections::{HashMap, VecDeque}, fmt, hash::{DefaultHasher, Hash, Hasher}, thread::{self}};
+#![allow(clippy::needless_return, clippy::too_many_arguments)]
 
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{cmp::Ordering, collections::{HashMap, HashSet, VecDeque}, fmt, fs, hash::{Hash, Hasher}, io, path::Path, str::FromStr, sync::{Arc, atomic::{self, AtomicBool}}, thread::{self}, time::{Duration, Instant}};
 
+pub mod mcts;
+pub mod record;
+pub mod render;
+pub mod suite;
+pub mod tournament;
 
 
+// Whether a game is over and how it ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameState {
+    InProgress,
+    Won(Player),    // The player who won
+    Draw,
+}
+
+// One of the two players. Player One plays the o discs and moves first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Player {
+    One,
+    Two,
+}
+
+impl Player {
+    // The player whose turn it is after the given number of moves
+    pub fn from_turn(turn: u8) -> Self {
+        match turn%2 {
+            0 => Player::One,
+            _ => Player::Two
+        }
+    }
+
+    // Gives the player with the given number, 1 or 2
+    pub fn from_number(number: u8) -> Option<Self> {
+        match number {
+            1 => Some(Player::One),
+            2 => Some(Player::Two),
+            _ => None
+        }
+    }
+
+    // The player's number, which is also the value of the player's discs in the grid
+    pub fn number(self) -> u8 {
+        match self {
+            Player::One => 1,
+            Player::Two => 2
+        }
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            Player::One => Player::Two,
+            Player::Two => Player::One
+        }
+    }
+}
+
+impl From<Player> for u8 {
+    fn from(player: Player) -> u8 {
+        player.number()
+    }
+}
+
+// Stage of a game, see Grid::stage()
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameStage {
+    Opening,
+    Midgame,
+    Endgame,
+}
+
+// Fill ratios where the opening and the midgame end
+const OPENING_FILL_RATIO: f64 = 0.25;
+const MIDGAME_FILL_RATIO: f64 = 0.6;
+
+// Length of the vector returned by Grid::feature_vector()
+pub const N_FEATURES: usize = 8;
+
+// With the serde feature a grid is serialized as its settings, discs and turn, see GridData
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "GridData", into = "GridData"))]
 pub struct Grid {
     l: usize,       // Length of disc-line required to win
     w: usize,
     h: usize,
-    vec: Vec<u8>,   // 0: empty [ ], 1: player 1 [o], 2: player 2 [x], 10: player 1 highlighted, 20: player 2 highlighted
+    vec: Vec<u8>,   // 0: empty [ ], 1: player 1 [o], 2: player 2 [x]
     turn: u8,
+    highlight: Vec<bool>,   // Positions of winning lines, parallel to vec. Empty if nothing is highlighted.
+    zobrist: u64,           // Zobrist hash of the discs, updated whenever a position is set
+    mirror_zobrist: u64,    // Zobrist hash of the discs of the grid reflected left to right
+    masks: [u128; 2],       // Bitboards of the discs of player 1 and 2, only kept if the grid fits, see bit_index
 }
 
 impl Grid {
@@ -17,19 +100,28 @@ impl Grid {
     // First index specifies row, with 0 being the bottom row
     // Second index specifies the column with 0 being the leftmost column
     pub fn new(l: usize, w: usize, h: usize) -> Self{
-        Grid { l, w, h, vec: vec![0; w*h], turn: 0 }
+        Grid { l, w, h, vec: vec![0; w*h], turn: 0, highlight: Vec::new(), zobrist: 0, mirror_zobrist: 0, masks: [0; 2] }
     }
 
     pub fn width(&self) -> usize {
         self.w
     }
 
+    pub fn height(&self) -> usize {
+        self.h
+    }
+
+    // Number of consecutive discs needed to win
+    pub fn win_length(&self) -> usize {
+        self.l
+    }
+
     pub fn turn(&self) -> u8 {
         self.turn
     }
 
-    pub fn player_to_move(&self) -> u8 {
-        return self.turn%2 + 1
+    pub fn player_to_move(&self) -> Player {
+        return Player::from_turn(self.turn)
     }
 
     fn read(&self, i: usize, j: usize) -> u8 {
@@ -38,10 +130,83 @@ impl Grid {
 
     fn set(&mut self, i: usize, j: usize, value: u8) {
         if (0..self.h).contains(&i) && (0..self.w).contains(&j) {
-            self.vec[i*self.w + j] = value
+            let index = i*self.w + j;
+            self.zobrist ^= zobrist_key(index, self.vec[index]) ^ zobrist_key(index, value);
+            let mirror_index = i*self.w + self.w-1-j;
+            self.mirror_zobrist ^= zobrist_key(mirror_index, self.vec[index]) ^ zobrist_key(mirror_index, value);
+            if let Some(bit) = self.bit_index(i, j) {
+                for (player, mask) in [1, 2].into_iter().zip(self.masks.iter_mut()) {
+                    match value == player {
+                        true => *mask |= 1 << bit,
+                        false => *mask &= !(1 << bit)
+                    }
+                }
+            }
+            self.vec[index] = value
         }
     }
 
+    // Position of (i,j) in the bitboards, or None if the grid is too large for them.
+    // The columns are stored one after the other from the bottom up, each followed by an always empty bit.
+    // The empty bits keep lines from wrapping around from the top of one column to the bottom of the next.
+    fn bit_index(&self, i: usize, j: usize) -> Option<usize> {
+        match (self.h+1)*self.w <= 128 {
+            true => Some(j*(self.h+1) + i),
+            false => None
+        }
+    }
+
+    // Checks with the bitboards if the player has a line through the bit of (row,col).
+    // For each direction a bit of `starts` is set where `run` discs in a row of the player begin.
+    // Shifting and ANDing doubles the run until it reaches l, so l=4 takes two shifts per direction.
+    fn bitboard_line(&self, bit: usize, player: u8) -> bool {
+        let mask = self.masks[player as usize - 1];
+        let l = self.l;
+
+        // Shifts to the next position: up, right, up-right and down-right
+        for shift in [1, self.h+1, self.h+2, self.h] {
+            let mut starts = mask;
+            let mut run = 1;
+            while run < l && starts != 0 {
+                let step = run.min(l - run);
+                match step*shift < 128 {
+                    true => starts &= starts >> (step*shift),
+                    false => starts = 0
+                }
+                run += step;
+            }
+
+            // A line through bit begins at most l-1 positions before it
+            for k in 0..l {
+                if k*shift <= bit && starts & (1 << (bit - k*shift)) != 0 {
+                    return true
+                }
+            }
+        }
+        false
+    }
+
+    fn is_highlighted(&self, i: usize, j: usize) -> bool {
+        !self.highlight.is_empty() && self.highlight[i*self.w + j]
+    }
+
+    fn set_highlight(&mut self, i: usize, j: usize) {
+        if self.highlight.is_empty() {
+            self.highlight = vec![false; self.w*self.h];
+        }
+        self.highlight[i*self.w + j] = true
+    }
+
+    // Removes the highlighting of winning lines
+    pub fn clear_highlight(&mut self) {
+        self.highlight.clear()
+    }
+
+    // Checks if a disc can be played in the column, i.e. the column exists and is not full
+    pub fn can_play(&self, col: usize) -> bool {
+        col < self.w && self.read(self.h-1, col) == 0
+    }
+
     // Gives a vector with the indices of all non-full columns 
     pub fn legal_moves(&self) -> Vec<usize> {
         let mut legal = Vec::new();
@@ -70,29 +235,65 @@ impl Grid {
     
     // Plays in a disc in  given column
     pub fn play(&mut self, col: usize) -> usize {
-        // Returns the position where the played disc landed
+        // Returns the position where the played disc landed,
+        // or an illegal position if the move was rejected. try_play tells why a move was rejected.
+        self.try_play(col).unwrap_or(self.h)
+    }
+
+    // Plays a disc in the given column and returns the row where it landed.
+    // A rejected move leaves the grid unchanged, the turn counter included.
+    pub fn try_play(&mut self, col: usize) -> Result<usize, MoveError> {
+        if col >= self.w {
+            return Err(MoveError::OutOfBounds { col, width: self.w })
+        }
         for row in 0..self.h {
             if self.read(row, col) == 0 {
-                self.set(row, col, self.player_to_move());
+                self.set(row, col, self.player_to_move().number());
                 self.turn += 1;
-                return row
+                return Ok(row)
             }
         }
+        Err(MoveError::ColumnFull(col))
+    }
+
+    // Takes back the top disc of the given column, if it belongs to the player who moved last.
+    // Returns the row the disc was removed from, or None if the column is empty, doesn't exist
+    // or has the other player's disc on top, since taking that back would leave a grid that can't be reached.
+    // Highlighting is removed, since the disc may have been part of a highlighted line.
+    pub fn unplay(&mut self, col: usize) -> Option<usize> {
+        if col >= self.w || self.turn == 0 {
+            return None
+        }
+        let row = (0..self.h).rev().find(|&row| self.read(row, col) != 0)?;
+        if self.read(row, col) != self.player_to_move().other().number() {
+            return None
+        }
 
-        // Returns an illegal position if the column was full
-        self.h
+        self.set(row, col, 0);
+        self.turn -= 1;
+        self.clear_highlight();
+        Some(row)
     }
 
     
-    // Gives all possible grid states that can be reached with one move 
-    fn next_grids(&self) -> HashMap<[usize; 2], Grid> {
-        let legal_moves = self.legal_moves();
-        let mut grids = HashMap::with_capacity(legal_moves.len());
+    // Legal moves ordered from the center outwards, left before right at equal distance.
+    // Central moves tend to be stronger, so searching them first gives more pruning.
+    fn center_first_moves(&self) -> Vec<usize> {
+        let mut moves = self.legal_moves();
+        moves.sort_by_key(|&col| (2*col).abs_diff(self.w - 1));
+        moves
+    }
+
+    // Gives all possible grid states that can be reached with one move, in center first order.
+    // Every child is a clone, so the searches play and take back moves instead and this is only used where the grids are kept.
+    fn next_grids(&self) -> Vec<([usize; 2], Grid)> {
+        let legal_moves = self.center_first_moves();
+        let mut grids = Vec::with_capacity(legal_moves.len());
         
         for col in legal_moves {
             let mut grid = self.clone();
             let row = grid.play(col);
-            grids.insert([row, col], grid);
+            grids.push(([row, col], grid));
         }
         grids
     }
@@ -103,6 +304,14 @@ impl Grid {
     // Used when exploring the move tree to check if a disc played at (row, col) results in victory.
     fn win_fast(&self, row: usize, col: usize) -> u8 {
         let player = self.read(row, col);
+
+        // Grids that fit in the bitboards are checked with a few bitwise operations
+        if let Some(bit) = self.bit_index(row, col) {
+            return match player != 0 && self.bitboard_line(bit, player) {
+                true => player,
+                false => 0
+            }
+        }
         
         // Vertical line
         if row >= self.l-1 {
@@ -184,337 +393,1060 @@ impl Grid {
         return 0
     }
 
+
+    // Like win_fast, but also gives the winning line: all consecutive discs of the winner through (row, col)
+    // in the direction of the first line that was found, from one end to the other.
+    // The directions are checked in the same order as in win_fast: vertical, horizontal, / and \.
+    // Slower than win_fast, so it is meant for annotating moves rather than for the search.
+    pub fn win_fast_line(&self, row: usize, col: usize) -> Option<(Player, Vec<(usize, usize)>)> {
+        if row >= self.h || col >= self.w {
+            return None
+        }
+        let player = Player::from_number(self.read(row, col))?;
+
+        for (v_i, v_j) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+            let mut line = self.walk_line(row, col, -v_i, -v_j, player.number());
+            line.reverse();
+            line.push((row, col));
+            line.extend(self.walk_line(row, col, v_i, v_j, player.number()));
+            if line.len() >= self.l {
+                return Some((player, line))
+            }
+        }
+        None
+    }
+
+    // Positions of the player's discs that follow (row, col) in steps of (v_i, v_j), up to the first other position or wall
+    fn walk_line(&self, row: usize, col: usize, v_i: isize, v_j: isize, player: u8) -> Vec<(usize, usize)> {
+        let mut line = Vec::new();
+
+        // Signed position so that stepping off the low edge gives -1 instead of wrapping around
+        let (mut pos_i, mut pos_j) = (row as isize + v_i, col as isize + v_j);
+        while (0..self.h as isize).contains(&pos_i) && (0..self.w as isize).contains(&pos_j) 
+            && self.read(pos_i as usize, pos_j as usize) == player {
+            line.push((pos_i as usize, pos_j as usize));
+            pos_i += v_i;
+            pos_j += v_j;
+        }
+        line
+    }
+
     
     // Starts at (i,j) and walks with step velocity (v_i,v_j) until it hits a wall.
-    // If an l-length continuous line of discs of the same type is found, then the discs
-    // in that line will be highlighted. Returns which player won or 0 if no one won.
-    fn walk_highlight(&mut self, mut i: usize, mut j: usize, v_i: i8, v_j: i8) -> u8 {
-        let mut p1_line: Vec<(usize, usize)> = Vec::with_capacity(self.l);
-        let mut p2_line: Vec<(usize, usize)> = Vec::with_capacity(self.l);
-        while (0..self.h).contains(&i) && (0..self.w).contains(&j) {
-            match self.read(i,j) {
-                1 => {p1_line.push((i,j)); p2_line.clear()},
-                2 => {p1_line.clear(); p2_line.push((i,j))},
-                _ => {p1_line.clear(); p2_line.clear()}
-            }
-
-            // Highlights by changing 1 --> 10, 2 --> 20. 
-            if p1_line.len() >= self.l {
-                for (i,j) in p1_line {
-                    self.set(i, j, 10*self.read(i, j))
-                }
-                return 1
-            } else if p2_line.len() >= self.l {
-                for (i,j) in p2_line {
-                    self.set(i, j, 10*self.read(i, j))
+    // Every continuous line of at least l discs of the winner is highlighted, all of its discs.
+    // If winner is 0, the player of the first such line becomes the winner.
+    // Returns the winner, which is still 0 if no line was found.
+    fn walk_highlight(&mut self, i: usize, j: usize, v_i: isize, v_j: isize, mut winner: u8) -> u8 {
+        let mut line: Vec<(usize, usize)> = Vec::with_capacity(self.l);
+        let mut line_player = 0;
+
+        // Signed position so that stepping off the low edge gives -1 instead of wrapping around
+        let (mut pos_i, mut pos_j) = (i as isize, j as isize);
+        while (0..self.h as isize).contains(&pos_i) && (0..self.w as isize).contains(&pos_j) {
+            let (i, j) = (pos_i as usize, pos_j as usize);
+            let disc = self.read(i,j);
+            if disc != line_player {
+                line.clear();
+                line_player = disc;
+            }
+            line.push((i,j));
+
+            // The whole line is highlighted again for every disc that makes it longer
+            if line_player != 0 && line.len() >= self.l && (winner == 0 || winner == line_player) {
+                winner = line_player;
+                for &(i,j) in &line {
+                    self.set_highlight(i, j)
                 }
-                return 2
             }
-            i = ((i as i8) + v_i) as usize;
-            j = ((j as i8) + v_j) as usize;
+            pos_i += v_i;
+            pos_j += v_j;
         }
-        0
+        winner
     }
 
 
-    // Checks if any player has won and highlights the winning line.
-    // Slower than self.win_fast() but checks the whole grid. 
-    pub fn win_highlight(&mut self) -> u8 {
+    // Checks if any player has won and highlights all winning lines of the winner,
+    // so that a move that completes two lines at once shows both.
+    // Slower than self.win_fast() but checks the whole grid. Gives None if no one has won.
+    pub fn win_highlight(&mut self) -> Option<Player> {
+        let mut winner = 0;
+
         // Horizontal lines
         for i in 0..self.h {
-            match self.walk_highlight(i, 0, 0, 1) {
-                win if win != 0 => return win, _ => ()
-            };
+            winner = self.walk_highlight(i, 0, 0, 1, winner);
         }
         // Vertical lines
         for j in 0..self.w {
-            match self.walk_highlight(0, j, 1, 0) {
-                win if win != 0 => return win, _ => ()
-            };
+            winner = self.walk_highlight(0, j, 1, 0, winner);
         }
         // Diagonals
-        for i in 1..=(self.h-self.l) {
-            match self.walk_highlight(i, 0, 1, 1) {
-                win if win != 0 => return win, _ => ()    // Upward from left side, excluding the corner
-            };
-            match self.walk_highlight(i, self.w-1, 1, -1) {
-                win if win != 0 => return win, _ => ()    // Upward from right side, excluding the corner
-            };
+        for i in 1..=self.h.saturating_sub(self.l) {
+            winner = self.walk_highlight(i, 0, 1, 1, winner);           // Upward from left side, excluding the corner
+            winner = self.walk_highlight(i, self.w-1, 1, -1, winner);   // Upward from right side, excluding the corner
         }
-        for j in 0..=(self.w-self.l) {
-            match self.walk_highlight(0, j, 1, 1) {
-                win if win != 0 => return win, _ => ()    // Rightward from bottom row
-            };
+        for j in 0..=self.w.saturating_sub(self.l) {
+            winner = self.walk_highlight(0, j, 1, 1, winner);           // Rightward from bottom row
         }
         for j in (self.l-1)..(self.w) {
-            match self.walk_highlight(0, j, 1, -1) {
-                win if win != 0 => return win, _ => ()    // Leftward from bottom row
-            };
+            winner = self.walk_highlight(0, j, 1, -1, winner);          // Leftward from bottom row
         }
-        0
+        Player::from_number(winner)
     }
 
-    
-}
-impl fmt::Display for Grid {
-    // Graphical representation of the grid. 
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut output = String::with_capacity(3*self.w*self.h);
-        for j in 0..self.w {
-            output = format!("{output} {j} ")
-        }
-        match self.player_to_move() {
-            1 => output = format!("{output}  (o) "),
-            2 => output = format!("{output}  (x) "),
-            _ => ()
+
+    // Highlights every winning line of both players, for variants where both players can have a line at the same time.
+    // Returns the players that have a winning line.
+    pub fn highlight_all(&mut self) -> HashSet<Player> {
+        let mut winners = HashSet::new();
+        for line in self.lines() {
+            let (i, j) = line[0];
+            let Some(player) = Player::from_number(self.read(i, j)) else {
+                continue
+            };
+            if line.iter().all(|&(i, j)| self.read(i, j) == player.number()) {
+                for (i, j) in line {
+                    self.set_highlight(i, j)
+                }
+                winners.insert(player);
+            }
         }
+        winners
+    }
+
+    // All lines of l consecutive positions on the grid: horizontal, vertical and both diagonals.
+    fn lines(&self) -> Vec<Vec<(usize, usize)>> {
+        let mut lines = Vec::new();
         for i in 0..self.h {
-            output = format!("{output}\n");
             for j in 0..self.w {
-                output = match self.read(self.h-i-1, j) {
-                    0 => format!("{output}[ ]"),
-                    1 => format!("{output}[o]"),
-                    2 => format!("{output}[x]"),
-                    10 => format!("{output} o "),   // Used when highlighting player 1 win
-                    20 => format!("{output} x "),   // Used when highlighting player 2 win
-                    _ => format!("{output}err")
+                let fits_right = j + self.l <= self.w;
+                let fits_up = i + self.l <= self.h;
+                let fits_left = j + 1 >= self.l;
+                if fits_right {
+                    lines.push((0..self.l).map(|k| (i, j+k)).collect());        // -
+                }
+                if fits_up {
+                    lines.push((0..self.l).map(|k| (i+k, j)).collect());        // |
+                }
+                if fits_up && fits_right {
+                    lines.push((0..self.l).map(|k| (i+k, j+k)).collect());      // /
+                }
+                if fits_up && fits_left {
+                    lines.push((0..self.l).map(|k| (i+k, j-k)).collect());      // \
                 }
             }
         }
-        write!(f, "{output}")
+        lines
     }
-}
 
-impl Hash for Grid {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.vec.hash(state);
+    // Number of lines that contain exactly n_discs of the player's discs and no enemy discs
+    fn count_runs(&self, player: u8, n_discs: usize) -> usize {
+        self.lines().iter()
+            .filter(|line| {
+                let own = line.iter().filter(|&&(i, j)| self.read(i, j) == player).count();
+                let empty = line.iter().filter(|&&(i, j)| self.read(i, j) == 0).count();
+                own == n_discs && own + empty == self.l
+            })
+            .count()
     }
-}
 
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
-}
+    // Empty positions on lines the player can still complete (lines without enemy discs), as (row, col).
+    // Ordered by the number of such lines through the position, most first, so the positions with the most
+    // leverage come first. Positions with an equal number are ordered by row and then column.
+    pub fn critical_cells(&self, player: Player) -> Vec<(usize, usize)> {
+        let mut counts = vec![0; self.h*self.w];
+        for line in self.lines() {
+            if line.iter().any(|&(i, j)| self.read(i, j) == player.other().number()) {
+                continue
+            }
+            for &(i, j) in &line {
+                if self.read(i, j) == 0 {
+                    counts[i*self.w + j] += 1;
+                }
+            }
+        }
 
+        let mut cells: Vec<(usize, usize)> = (0..self.h)
+            .flat_map(|i| (0..self.w).map(move |j| (i, j)))
+            .filter(|&(i, j)| counts[i*self.w + j] > 0)
+            .collect();
+        cells.sort_by_key(|&(i, j)| std::cmp::Reverse(counts[i*self.w + j]));
+        cells
+    }
 
-// Structure used for BFS exploration of the move tree
-struct Branch {
-    root: Grid,
-    queue: VecDeque<(f64, Grid)>,
-    score: f64,
-}
-impl Branch {
-    pub fn new(root_grid: Grid, queue_capacity: usize) -> Self {
-        Branch { root: root_grid, queue: VecDeque::with_capacity(queue_capacity), score: 0. }
+    // Checks if the player would win by having a disc in the empty position (row, col).
+    // Gravity is ignored, the position does not have to be playable.
+    fn wins_at(&self, row: usize, col: usize, player: u8) -> bool {
+        let mut grid = self.clone();
+        grid.set(row, col, player);
+        grid.win_fast(row, col) == player
     }
 
-    // Determines the score of this branch by searching through all possible combinations
-    // of moves to a given depth. The score is increased when paths to own victory is found
-    // and decreased when a paths to enemy victory is found. 
-    fn bfs(&mut self, protagonist: u8, depth: u8) {
-        let mut keep_pushing = true;
+    // Empty positions where a disc from the player would complete a line of length l
+    fn threat_cells(&self, player: u8) -> Vec<(usize, usize)> {
+        let mut threats = Vec::new();
+        for i in 0..self.h {
+            for j in 0..self.w {
+                if self.read(i, j) == 0 && self.wins_at(i, j, player) {
+                    threats.push((i, j))
+                }
+            }
+        }
+        threats
+    }
 
-        let relevance = 1.;
-        self.queue.push_back((relevance, self.root.clone()));
+    // Row where a disc played in the column would land. Gives self.h if the column is full.
+    fn landing_row(&self, col: usize) -> usize {
+        (0..self.h).find(|&row| self.read(row, col) == 0).unwrap_or(self.h)
+    }
 
-        while self.queue.len() > 0 {
-            let (relevance, grid) = self.queue.pop_front().unwrap();
+    // Positions (row, col) where a disc would land in each non-full column, from left to right
+    pub fn frontier(&self) -> Vec<(usize, usize)> {
+        self.legal_moves().into_iter()
+            .map(|col| (self.landing_row(col), col))
+            .collect()
+    }
 
-            for ([row, col], child) in grid.next_grids() {
-                match child.win_fast(row, col) {
-                    0 => {                      // No one wins
-                        if keep_pushing {
-                            if child.turn == self.root.turn + depth {
-                                keep_pushing = false;
-                                continue;
-                            }
-                            self.queue.push_back((relevance/grid.n_legal_f64(), child));
-                        }
-                    },
-                    // One loss has the same magnitude as legal_moves.len() wins  
-                    w if w == protagonist => {          // The protagonist wins
-                        self.score += relevance/grid.n_legal_f64();
-                    },
-                    _ => {                                  // The other player wins
-                        self.score -= relevance;       
-                    }
+    // Columns where the player would win by playing right now
+    pub fn immediate_threats(&self, player: Player) -> Vec<usize> {
+        self.legal_moves().into_iter()
+            .filter(|&col| self.wins_at(self.landing_row(col), col, player.number()))
+            .collect()
+    }
+
+    // Columns where a disc from the player would land directly below a position where the enemy would win.
+    // Playing there lets the enemy win on the next move.
+    pub fn forced_under_threat(&self, player: Player) -> Vec<usize> {
+        self.legal_moves().into_iter()
+            .filter(|&col| {
+                let row = self.landing_row(col);
+                row + 1 < self.h && self.wins_at(row + 1, col, player.other().number())
+            })
+            .collect()
+    }
+
+    // Columns the player can play without losing to an immediate threat from the enemy.
+    // These are the player's own winning moves and, if the enemy has exactly one immediate threat, the column that blocks it.
+    // If the enemy has no immediate threats then every legal move is safe, except the ones below an enemy threat.
+    // Gives an empty vector if the enemy has several immediate threats and the player can't win right away.
+    pub fn safe_replies(&self, player: Player) -> Vec<usize> {
+        let mut safe = self.immediate_threats(player);
+        let enemy_threats = self.immediate_threats(player.other());
+
+        match enemy_threats.len() {
+            0 => {
+                let under_threat = self.forced_under_threat(player);
+                let legal = self.legal_moves();
+                if under_threat.len() == legal.len() {
+                    return legal                    // Every move hands over a win
                 }
-            }
+                return legal.into_iter().filter(|col| safe.contains(col) || !under_threat.contains(col)).collect()
+            },
+            1 if !safe.contains(&enemy_threats[0]) => safe.push(enemy_threats[0]),
+            _ => ()
         }
+        safe
     }
-}
 
+    // Checks if the enemy has immediate threats in at least two columns. The player can only block one of them,
+    // so the player loses unless they can win first.
+    pub fn unblockable(&self, player: Player) -> bool {
+        self.immediate_threats(player.other()).len() >= 2
+    }
 
+    // Number of moves that can still be played before the grid is full
+    pub fn moves_remaining(&self) -> usize {
+        self.vec.iter().filter(|&&disc| disc == 0).count()
+    }
 
-// Determines the best possible move for a given player, based on a given search depth.
-// 
-// The depth should be greater than 1. Unresonably large depth causes memory allocation errors.
-// Uses multithreading. Makes one Branch from each top-level legal move and runs each in its own thread. 
-// 
-// Returns the index of the column whose branch has the highest score
-// 
-// Randomness is caused by internal reordering of the hashMaps when they are cloned.
-pub fn analyze_bfs_mt(grid: Grid, protagonist: u8, depth: u8) -> usize {
-    
- 
-    let relevance = 1./grid.n_legal_f64();
-    
-    let mut handles = Vec::new();
-    
-    // Try all possible moves
-    for ([row, col], branch_grid) in grid.next_grids() {
+    // Fraction of the grid that is filled with discs
+    pub fn fill_ratio(&self) -> f64 {
+        self.turn as f64/(self.w*self.h) as f64
+    }
 
-        let queue_capacity = (grid.n_legal_f64() as usize).pow((depth-1).into());
-        
-        // Spawn one thread per 1st level branch
-        handles.push(thread::spawn(move || {
+    // Stage of the game, decided by how full the grid is
+    pub fn stage(&self) -> GameStage {
+        match self.fill_ratio() {
+            ratio if ratio < OPENING_FILL_RATIO => GameStage::Opening,
+            ratio if ratio < MIDGAME_FILL_RATIO => GameStage::Midgame,
+            _ => GameStage::Endgame
+        }
+    }
 
-            match branch_grid.win_fast(row, col) {
-                0 => {                              // No one wins
-                    let mut branch = Branch::new(branch_grid, queue_capacity);
-                    branch.bfs(protagonist, depth-1);
+    // How sharp the position is. Grows with the number of legal moves, the number of immediate threats
+    // of both players and how full the grid is. Can be used to search deeper in sharp positions.
+    pub fn complexity(&self) -> f64 {
+        let threats = self.immediate_threats(Player::One).len() + self.immediate_threats(Player::Two).len();
+        self.n_legal_f64() + 3.*threats as f64 + 2.*self.fill_ratio()
+    }
 
-                    return (col, relevance*branch.score)
-                },
-                // One loss has the same magnitude as legal_moves.len() wins  
-                w if w == protagonist => {           // The protagonist wins
-                    return (col, relevance);       
-                },
-                _ => panic!()       // This should be unreachable since the analysis is only run during the protagonist's turn
-            }
-        }));
+    // Number of the player's discs in the center column, or the two center columns if the width is even
+    fn center_discs(&self, player: u8) -> usize {
+        let center_cols = if self.w%2 == 1 {self.w/2..=self.w/2} else {self.w/2-1..=self.w/2};
+        center_cols
+            .map(|j| (0..self.h).filter(|&i| self.read(i, j) == player).count())
+            .sum()
     }
-    
-    let best_col = handles.into_iter()
-        .map(|handle| handle.join().unwrap())
-        .max_by(|(_, a), (_, b)| a.total_cmp(b))
-        .map(|(col, _)| col);
-    
-    best_col.unwrap()
-    
-}
 
+    // Features describing the position from the player's perspective, intended as input for a learned evaluator.
+    // The vector always has N_FEATURES entries, given for the player first and then for the enemy:
+    //      0, 1: lines with 2 own discs and no enemy discs (open twos)
+    //      2, 3: lines with 3 own discs and no enemy discs (open threes)
+    //      4, 5: discs in the center column(s)
+    //      6, 7: threats on rows with the player's prefered parity (see Node::heuristic)
+    pub fn feature_vector(&self, player: Player) -> Vec<f64> {
+        let (player, enemy) = (player.number(), player.other().number());
+        let parity_threats = |p: u8| {
+            self.threat_cells(p).iter()
+                .filter(|&&(i, _)| (i%2 + 1) as u8 == p)
+                .count()
+        };
+
+        let features = vec![
+            self.count_runs(player, 2) as f64, self.count_runs(enemy, 2) as f64,
+            self.count_runs(player, 3) as f64, self.count_runs(enemy, 3) as f64,
+            self.center_discs(player) as f64, self.center_discs(enemy) as f64,
+            parity_threats(player) as f64, parity_threats(enemy) as f64,
+        ];
+        debug_assert_eq!(features.len(), N_FEATURES);
+        features
+    }
 
-#[derive(Clone)]
-struct ThreatMap {
-    w: usize,
-    h: usize,
-    horizontal1: Vec<f64>,
-    horizontal2: Vec<f64>,
-    fwrd_slash1: Vec<f64>,
-    fwrd_slash2: Vec<f64>,
-    back_slash1: Vec<f64>,
-    back_slash2: Vec<f64>,
-}
+    // Parity of every cell, indexed as [row][col] with row 0 at the bottom, for shading in a user interface.
+    // Empty cells on odd rows (counting the bottom row as row 1) are labeled Player::One and on even rows Player::Two.
+    // The label is the player who prefers threats on that row, like in Node::heuristic. Occupied cells are None.
+    pub fn parity_map(&self) -> Vec<Vec<Option<Player>>> {
+        (0..self.h).map(|i| {
+            (0..self.w).map(|j| match self.read(i, j) {
+                0 => Some(Player::from_turn(i as u8)),
+                _ => None
+            }).collect()
+        }).collect()
+    }
 
-impl ThreatMap {
-    // Contains 6 grids that store the threat level of empty disc positions on the grid .
-    // The grids are indexed by threat shape: '-', '/', '\'; and player: 1, 2.  
-    // All grid positions start with threat level 1.
-    // Non-empty grid positions have have threat level 0.
 
-    fn new(grid: &Grid) -> Self {
-        let w = grid.w;
-        let h = grid.h;
-        
-        ThreatMap { w, h, horizontal1: vec![1.; w*h], horizontal2: vec![1.; w*h],
-                          fwrd_slash1: vec![1.; w*h], fwrd_slash2: vec![1.; w*h],
-                          back_slash1: vec![1.; w*h], back_slash2: vec![1.; w*h]
+    // Compact text encoding of the grid: the format version "v1;" and "l,w,h:" followed by the columns from left
+    // to right, separated by '/'. Each column lists its discs from the bottom up as 'o' (player 1) or 'x' (player 2).
+    // The empty standard grid is "v1;4,7,6://////". Highlighting is not encoded.
+    pub fn to_code(&self) -> String {
+        let mut columns = Vec::with_capacity(self.w);
+        for j in 0..self.w {
+            let mut column = String::with_capacity(self.h);
+            for i in 0..self.h {
+                match self.read(i, j) {
+                    1 => column.push('o'),
+                    2 => column.push('x'),
+                    _ => break
+                }
+            }
+            columns.push(column);
         }
+        format!("v{CODE_VERSION};{},{},{}:{}", self.l, self.w, self.h, columns.join("/"))
     }
 
+    // Reads a grid in the layout of the Display implementation, e.g. a position saved from the terminal:
+    //       0  1  2  3   (x)
+    //      [ ][ ][ ][ ]
+    //      [ ][o][ ][ ]
+    //      [x][o][ ][ ]
+    // The header line with the column numbers is optional, and highlighted discs (" o ") are highlighted again.
+    // The width and height come from the rows, l has to be given. The turn is inferred from the number of discs.
+    pub fn from_board_str(board: &str, l: usize) -> Result<Self, ParseGridError> {
+        if l == 0 {
+            return Err(ParseGridError::InvalidSetting(l.to_string()))
+        }
+        let mut lines: Vec<&str> = board.lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        // The header is the only line without brackets. It gives the width and with that the width of the cells.
+        let mut cell = None;
+        if lines.first().is_some_and(|line| !line.contains('[')) {
+            let header = lines.remove(0);
+            let w = header.split_whitespace().filter(|token| token.parse::<usize>().is_ok()).count();
+            cell = Some(render::cell_width(w));
+        }
+        let cell = cell
+            .or_else(|| lines.iter().find(|line| line.starts_with('[')).and_then(|line| line.chars().position(|c| c == ']')).map(|k| k + 1))
+            .unwrap_or(3);
+
+        // Trailing spaces after a highlighted disc in the last column may have been trimmed
+        let h = lines.len();
+        let w = lines.first().map_or(0, |line| line.chars().count().div_ceil(cell));
+        if h == 0 || w == 0 {
+            return Err(ParseGridError::EmptyBoard)
+        }
 
-    fn read(&self, i: usize, j: usize, threat_shape: char, player: u8) -> f64 {
-        match threat_shape {
-            '-' => {
-                match player {
-                    1 => return self.horizontal1[i*self.w + j],
-                    2 => return self.horizontal2[i*self.w + j],
-                    _ => panic!()
-                }
+        let mut grid = Grid::new(l, w, h);
+        for (k, line) in lines.iter().enumerate() {
+            let mut chars: Vec<char> = line.chars().collect();
+            if chars.len() + 1 == w*cell {
+                chars.push(' ');
             }
-            '/' => {
-                match player {
-                    1 => return self.fwrd_slash1[i*self.w + j],
-                    2 => return self.fwrd_slash2[i*self.w + j],
-                    _ => panic!()
-                }
+            if chars.len() != w*cell {
+                return Err(ParseGridError::RaggedRow(k))
             }
-            '\\' => {
-                match player {
-                    1 => return self.back_slash1[i*self.w + j],
-                    2 => return self.back_slash2[i*self.w + j],
-                    _ => panic!()
+
+            let i = h - 1 - k;
+            for (j, cell_chars) in chars.chunks(cell).enumerate() {
+                let text: String = cell_chars.iter().collect();
+                let highlighted = match (cell_chars[0], cell_chars[cell-1]) {
+                    ('[', ']') => false,
+                    (' ', ' ') => true,
+                    _ => return Err(ParseGridError::InvalidCell(text))
+                };
+                // The cells are counted in chars, so the content is taken from the chars instead of slicing the text
+                let content: String = cell_chars[1..cell-1].iter().collect();
+                let player = match (content.trim(), highlighted) {
+                    ("", false) => 0,
+                    ("o", _) => 1,
+                    ("x", _) => 2,
+                    _ => return Err(ParseGridError::InvalidCell(text))
+                };
+                if player == 0 {
+                    continue
+                }
+                grid.set(i, j, player);
+                if highlighted {
+                    grid.set_highlight(i, j);
                 }
             }
-            _ => panic!()
         }
+        grid.turn = grid.reachable_turn()?;
+
+        Ok(grid)
     }
 
-    fn increment(&mut self, i: usize, j: usize, threat_shape: char, player: u8) {
-        if (0..self.h).contains(&i) && (0..self.w).contains(&j) {
-            match threat_shape {
-                '-' => {
-                    match player {
-                        1 => self.horizontal1[i*self.w + j] += 1.,
-                        2 => self.horizontal2[i*self.w + j] += 1.,
-                        _ => panic!()
-                    }
-                }
-                '/' => {
-                    match player {
-                        1 => self.fwrd_slash1[i*self.w + j] += 1.,
-                        2 => self.fwrd_slash2[i*self.w + j] += 1.,
-                        _ => panic!()
-                    }
-                }
-                '\\' => {
-                    match player {
-                        1 => self.back_slash1[i*self.w + j] += 1.,
-                        2 => self.back_slash2[i*self.w + j] += 1.,
-                        _ => panic!()
-                    }
+    // Checks that the discs of a parsed grid could have been played in a real game and gives the turn they imply.
+    // Discs always rest on the bottom or on another disc, and player 1 always moves first,
+    // so it has the same number of discs as player 2 or one more.
+    // Shared by all the parsers, so that they accept the same grids.
+    fn reachable_turn(&self) -> Result<u8, ParseGridError> {
+        for i in 1..self.h {
+            for j in 0..self.w {
+                if self.read(i, j) != 0 && self.read(i-1, j) == 0 {
+                    return Err(ParseGridError::FloatingDisc { row: i, col: j })
                 }
-                _ => panic!()
             }
         }
+
+        let n_discs = [1, 2].map(|player| self.vec.iter().filter(|&&value| value == player).count());
+        if n_discs[0] != n_discs[1] && n_discs[0] != n_discs[1] + 1 {
+            return Err(ParseGridError::DiscCount)
+        }
+        Ok((n_discs[0] + n_discs[1]) as u8)
     }
-    fn nullify(&mut self, i: usize, j: usize, threat_shape: char, player: u8) {
-        if (0..self.h).contains(&i) && (0..self.w).contains(&j) {
-            match threat_shape {
-                '-' => {
-                    match player {
-                        1 => self.horizontal1[i*self.w + j] = 0.,
-                        2 => self.horizontal2[i*self.w + j] = 0.,
-                        _ => panic!()
-                    }
-                }
-                '/' => {
-                    match player {
-                        1 => self.fwrd_slash1[i*self.w + j] = 0.,
-                        2 => self.fwrd_slash2[i*self.w + j] = 0.,
-                        _ => panic!()
-                    }
-                }
-                '\\' => {
-                    match player {
-                        1 => self.back_slash1[i*self.w + j] = 0.,
-                        2 => self.back_slash2[i*self.w + j] = 0.,
-                        _ => panic!()
-                    }
-                }
-                _ => panic!()
+
+    // Gives every grid from which the last player could have reached this grid with one move,
+    // by taking the last player's disc off the top of a column. Grids where a player had already
+    // won are left out, since the game would have ended there.
+    pub fn predecessors(&self) -> Vec<Grid> {
+        if self.turn == 0 {
+            return Vec::new()
+        }
+        let last_player = self.player_to_move().other().number();
+
+        let mut predecessors = Vec::new();
+        for col in 0..self.w {
+            let row = self.landing_row(col);
+            if row == 0 || self.read(row-1, col) != last_player {
+                continue
+            }
+
+            let mut grid = self.clone();
+            grid.set(row-1, col, 0);
+            grid.turn -= 1;
+            if grid.clone().win_highlight().is_none() {
+                predecessors.push(grid);
             }
         }
+        predecessors
     }
 
-    // Update the threatmap after player plays in (i,j)
-    fn update_with(&mut self, row: usize, col: usize, grid: &Grid) {
-        let player = grid.read(row, col);
-        
+    // Checks the whole grid to see if the game is over
+    pub fn game_state(&self) -> GameState {
+        match self.clone().win_highlight() {
+            None if self.legal_moves().is_empty() => GameState::Draw,
+            None => GameState::InProgress,
+            Some(winner) => GameState::Won(winner)
+        }
+    }
+
+    // Plays from this grid until the game is over, with the policy choosing the column for every move.
+    // If the policy chooses an illegal move, the leftmost legal move is played instead.
+    // The grid itself is not changed.
+    pub fn rollout(&self, mut policy: impl FnMut(&Grid) -> usize) -> GameState {
+        let state = self.game_state();
+        if state != GameState::InProgress {
+            return state
+        }
+
+        let mut grid = self.clone();
+        loop {
+            let legal_moves = grid.legal_moves();
+            if legal_moves.is_empty() {
+                return GameState::Draw
+            }
+
+            let mut col = policy(&grid);
+            if !legal_moves.contains(&col) {
+                col = legal_moves[0];
+            }
+
+            let row = grid.play(col);
+            if let Some(winner) = Player::from_number(grid.win_fast(row, col)) {
+                return GameState::Won(winner)
+            }
+        }
+    }
+
+    // Checks if the grid is the same when reflected left to right
+    fn is_symmetric(&self) -> bool {
+        (0..self.h).all(|i| (0..self.w/2).all(|j| self.read(i, j) == self.read(i, self.w-1-j)))
+    }
+
+    // Gives the grid reflected left to right
+    pub fn mirror(&self) -> Grid {
+        let mut mirrored = self.clone();
+        mirrored.clear_highlight();
+        for i in 0..self.h {
+            for j in 0..self.w {
+                mirrored.set(i, self.w-1-j, self.read(i, j));
+                if self.is_highlighted(i, j) {
+                    mirrored.set_highlight(i, self.w-1-j);
+                }
+            }
+        }
+        mirrored
+    }
+
+    // Hash that identifies the position in the transposition table.
+    // It is a Zobrist hash that is kept up to date as discs are played, so reading it is free.
+    pub fn position_hash(&self) -> u64 {
+        self.zobrist
+    }
+
+    // Hash that is the same for the grid and its mirror image: the smaller of their position hashes.
+    // Mirror images have the same value, so the transposition table stores them under this hash.
+    pub fn canonical_hash(&self) -> u64 {
+        self.zobrist.min(self.mirror_zobrist)
+    }
+
+    // Single line description of the grid for logs, e.g. "7x6 l=4 turn=2 tomove=o v1;4,7,6:/o/x////"
+    pub fn summary(&self) -> String {
+        let to_move = match self.player_to_move() {
+            Player::One => 'o',
+            Player::Two => 'x'
+        };
+        format!("{}x{} l={} turn={} tomove={} {}", self.w, self.h, self.l, self.turn, to_move, self.to_code())
+    }
+}
+impl fmt::Display for Grid {
+    // Graphical representation of the grid. 
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.renderer())
+    }
+}
+
+// Version of the format written by Grid::to_code(). Codes without a version are read as version 1.
+pub const CODE_VERSION: u32 = 1;
+
+// Reasons why a text could not be turned into a grid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseGridError {
+    MissingSettings,                                    // No "l,w,h:" prefix
+    InvalidSetting(String),                             // A setting is not a positive integer
+    ColumnCount { expected: usize, found: usize },      // Wrong number of columns for the width
+    ColumnOverflow(usize),                              // Column has more discs than the height allows
+    InvalidDisc(char),                                  // Character other than 'o' or 'x'
+    DiscCount,                                          // Disc counts can't result from alternating turns
+    InvalidVersion(String),                             // The "v<number>;" prefix has no valid number
+    UnsupportedVersion(u32),                            // Written by a newer version of the format
+    EmptyBoard,                                         // A board text without rows
+    RaggedRow(usize),                                   // Row (counted from the top) with the wrong length
+    InvalidCell(String),                                // Cell that is not "[ ]", "[o]", "[x]", " o " or " x "
+    FloatingDisc { row: usize, col: usize },            // Disc above an empty position
+    CellCount { expected: usize, found: usize },        // Wrong number of cells for the grid dimensions
+    InvalidCellValue(u8),                               // Cell value other than 0, 1 or 2
+    TurnCount { expected: u8, found: u8 },              // Turn that doesn't match the number of discs
+}
+
+impl fmt::Display for ParseGridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseGridError::MissingSettings => write!(f, "expected the grid settings \"l,w,h:\" before the columns"),
+            ParseGridError::InvalidSetting(setting) => write!(f, "invalid grid setting \"{setting}\""),
+            ParseGridError::ColumnCount { expected, found } => write!(f, "expected {expected} columns but found {found}"),
+            ParseGridError::ColumnOverflow(col) => write!(f, "column {col} has more discs than the grid height"),
+            ParseGridError::InvalidDisc(disc) => write!(f, "invalid disc '{disc}', expected 'o' or 'x'"),
+            ParseGridError::DiscCount => write!(f, "the number of o and x discs is not reachable by alternating turns"),
+            ParseGridError::InvalidVersion(version) => write!(f, "invalid format version \"{version}\""),
+            ParseGridError::UnsupportedVersion(version) => 
+                write!(f, "format version {version} is not supported, the newest supported version is {CODE_VERSION}"),
+            ParseGridError::EmptyBoard => write!(f, "the board has no rows"),
+            ParseGridError::RaggedRow(row) => write!(f, "row {} from the top has a different width than the first row", row+1),
+            ParseGridError::InvalidCell(cell) => write!(f, "invalid cell \"{cell}\", expected \"[ ]\", \"[o]\" or \"[x]\""),
+            ParseGridError::FloatingDisc { row, col } => write!(f, "the disc in row {row}, column {col} has no disc below it"),
+            ParseGridError::CellCount { expected, found } => write!(f, "expected {expected} cells but found {found}"),
+            ParseGridError::InvalidCellValue(value) => write!(f, "invalid cell value {value}, expected 0, 1 or 2"),
+            ParseGridError::TurnCount { expected, found } => write!(f, "the grid has {expected} discs but the turn is {found}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseGridError {}
+
+// Reasons why a move was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    ColumnFull(usize),                                  // No empty position left in the column
+    OutOfBounds { col: usize, width: usize },           // Column index not smaller than the width
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::ColumnFull(col) => write!(f, "column {col} is full"),
+            MoveError::OutOfBounds { col, width } => write!(f, "column {col} does not exist, the grid has {width} columns"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+impl FromStr for Grid {
+    type Err = ParseGridError;
+
+    // Parses the compact code produced by Grid::to_code(), e.g. "v1;4,7,6:/o/xo////".
+    // The version prefix may be left out. The turn is inferred from the number of discs.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut code = s.trim();
+        if let Some((version, rest)) = code.strip_prefix('v').and_then(|versioned| versioned.split_once(';')) {
+            let version = version.parse::<u32>()
+                .map_err(|_| ParseGridError::InvalidVersion(version.to_string()))?;
+            if version != CODE_VERSION {
+                return Err(ParseGridError::UnsupportedVersion(version))
+            }
+            code = rest;
+        }
+
+        let (settings, columns) = code.split_once(':').ok_or(ParseGridError::MissingSettings)?;
+
+        let settings: Vec<usize> = settings.split(',')
+            .map(|setting| match setting.trim().parse::<usize>() {
+                Ok(value) if value > 0 => Ok(value),
+                _ => Err(ParseGridError::InvalidSetting(setting.trim().to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        let [l, w, h] = settings[..] else {
+            return Err(ParseGridError::MissingSettings)
+        };
+
+        let columns: Vec<&str> = columns.split('/').collect();
+        if columns.len() != w {
+            return Err(ParseGridError::ColumnCount { expected: w, found: columns.len() })
+        }
+
+        let mut grid = Grid::new(l, w, h);
+        for (j, column) in columns.iter().enumerate() {
+            if column.chars().count() > h {
+                return Err(ParseGridError::ColumnOverflow(j))
+            }
+            for (i, disc) in column.chars().enumerate() {
+                let player = match disc {
+                    'o' => 1,
+                    'x' => 2,
+                    _ => return Err(ParseGridError::InvalidDisc(disc))
+                };
+                grid.set(i, j, player);
+            }
+        }
+        grid.turn = grid.reachable_turn()?;
+
+        Ok(grid)
+    }
+}
+
+// The fields of a grid that are serialized, the others are derived from them.
+// vec holds the cells row by row from the bottom, like Grid::vec.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GridData {
+    l: usize,
+    w: usize,
+    h: usize,
+    vec: Vec<u8>,
+    turn: u8,
+}
+
+#[cfg(feature = "serde")]
+impl From<Grid> for GridData {
+    fn from(grid: Grid) -> Self {
+        GridData { l: grid.l, w: grid.w, h: grid.h, vec: grid.vec, turn: grid.turn }
+    }
+}
+
+// Builds the grid with Grid::set, so that the hash and the bitboards are set up,
+// and checks it with the same rules as the other parsers.
+#[cfg(feature = "serde")]
+impl TryFrom<GridData> for Grid {
+    type Error = ParseGridError;
+
+    fn try_from(data: GridData) -> Result<Self, Self::Error> {
+        let GridData { l, w, h, vec, turn } = data;
+        if let Some(setting) = [l, w, h].into_iter().find(|&setting| setting == 0) {
+            return Err(ParseGridError::InvalidSetting(setting.to_string()))
+        }
+        // The product is checked, since the dimensions come from outside and could overflow
+        if w.checked_mul(h) != Some(vec.len()) {
+            return Err(ParseGridError::CellCount { expected: w.saturating_mul(h), found: vec.len() })
+        }
+
+        let mut grid = Grid::new(l, w, h);
+        for i in 0..h {
+            for j in 0..w {
+                match vec[i*w + j] {
+                    0 => continue,
+                    value @ (1 | 2) => grid.set(i, j, value),
+                    value => return Err(ParseGridError::InvalidCellValue(value))
+                }
+            }
+        }
+
+        let expected = grid.reachable_turn()?;
+        if turn != expected {
+            return Err(ParseGridError::TurnCount { expected, found: turn })
+        }
+        grid.turn = turn;
+
+        Ok(grid)
+    }
+}
+
+impl Hash for Grid {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.vec.hash(state);
+    }
+}
+
+// Grids are equal if they have the same settings and discs. Highlighting of winning lines is ignored,
+// so a grid equals itself before win_highlight was called. Equal grids have equal hashes.
+impl PartialEq for Grid {
+    fn eq(&self, other: &Self) -> bool {
+        self.l == other.l && self.w == other.w && self.h == other.h && self.vec == other.vec && self.turn == other.turn
+    }
+}
+
+impl Eq for Grid {}
+
+// Zobrist key of a disc of the player at the given index of Grid::vec, or 0 for an empty position.
+// The hash of a grid is the xor of the keys of all its discs, so it changes by one xor when a disc is played
+// or taken back. The keys are made from the index and the player with the splitmix64 mixer instead of a table
+// of random numbers, so they work for every grid size and are the same in every run.
+fn zobrist_key(index: usize, player: u8) -> u64 {
+    if player == 0 {
+        return 0
+    }
+    let mut z = (2*index as u64 + player as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+
+// Structure used for BFS exploration of the move tree
+struct Branch {
+    root: Grid,
+    queue: VecDeque<(f64, Grid)>,
+    score: f64,
+}
+impl Branch {
+    pub fn new(root_grid: Grid, queue_capacity: usize) -> Self {
+        Branch { root: root_grid, queue: VecDeque::with_capacity(queue_capacity), score: 0. }
+    }
+
+    // Determines the score of this branch by searching through all possible combinations
+    // of moves to a given depth. The score is increased when paths to own victory is found
+    // and decreased when a paths to enemy victory is found. 
+    // Stops early when cancel is set, the score then only counts the positions searched so far.
+    fn bfs(&mut self, protagonist: u8, depth: u8, cancel: &AtomicBool) {
+        let mut keep_pushing = true;
+
+        let relevance = 1.;
+        self.queue.push_back((relevance, self.root.clone()));
+
+        while !self.queue.is_empty() {
+            if cancel.load(atomic::Ordering::Relaxed) {
+                break
+            }
+            let (relevance, mut grid) = self.queue.pop_front().unwrap();
+            let n_legal = grid.n_legal_f64();
+
+            // Play each move on the popped grid and take it back, only grids that are queued get cloned
+            for col in grid.center_first_moves() {
+                let row = grid.play(col);
+                match grid.win_fast(row, col) {
+                    0 => {                      // No one wins
+                        if keep_pushing {
+                            if grid.turn == self.root.turn + depth {
+                                keep_pushing = false;
+                            } else {
+                                self.queue.push_back((relevance/n_legal, grid.clone()));
+                            }
+                        }
+                    },
+                    // One loss has the same magnitude as legal_moves.len() wins  
+                    w if w == protagonist => {          // The protagonist wins
+                        self.score += relevance/n_legal;
+                    },
+                    _ => {                                  // The other player wins
+                        self.score -= relevance;       
+                    }
+                }
+                grid.unplay(col);
+            }
+        }
+    }
+}
+
+
+
+// Upper limit on the estimated number of move sequences for a search to the end of the game
+const ENDGAME_TREE_SIZE: f64 = 1e6;
+
+// Gives a search depth that reaches the end of the game when few empty positions remain,
+// otherwise the base depth is returned unchanged.
+// 
+// The tree size is estimated as (number of legal moves)^(number of empty positions). Since there are
+// never more than 7 legal moves on the standard grid, the depth always reaches the end with 7 or fewer empty positions.
+pub fn endgame_depth(grid: &Grid, base: u8) -> u8 {
+    let empty = grid.moves_remaining();
+    
+    if grid.n_legal_f64().powi(empty as i32) <= ENDGAME_TREE_SIZE {
+        return base.max(empty.min(u8::MAX as usize) as u8)
+    }
+    base
+}
+
+
+// Reasons why an analysis could not be run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalysisError {
+    NotProtagonistsTurn { protagonist: Player, to_move: Player },   // The analysis is only done for the player to move
+    NoLegalMoves,                                           // The grid is full
+    DepthTooSmall { depth: u8, min: u8 },                   // The search can't run with this few moves
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalysisError::NotProtagonistsTurn { protagonist, to_move } => 
+                write!(f, "cannot analyze for player {} when it is player {}'s turn", protagonist.number(), to_move.number()),
+            AnalysisError::NoLegalMoves => write!(f, "there are no legal moves to analyze"),
+            AnalysisError::DepthTooSmall { depth, min } => 
+                write!(f, "search depth {depth} is too small, the minimum is {min}"),
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
+
+
+// Compares the number of immediate threats of the player and the enemy.
+// Threats of the player to move count double, since having the move with a threat wins the game.
+pub fn tempo_score(grid: &Grid, player: Player) -> f64 {
+    let own_threats = grid.immediate_threats(player).len() as f64;
+    let enemy_threats = grid.immediate_threats(player.other()).len() as f64;
+
+    match grid.player_to_move() {
+        p if p == player => 2.*own_threats - enemy_threats,
+        _ => own_threats - 2.*enemy_threats
+    }
+}
+
+
+// Largest number of grids that is allocated up front for the queue of a Branch
+const MAX_QUEUE_CAPACITY: usize = 1 << 20;
+
+// Smallest depth analyze_bfs_mt accepts
+pub const MIN_BFS_DEPTH: u8 = 2;
+
+// Determines the best possible move for a given player, based on a given search depth.
+// 
+// The depth should be at least MIN_BFS_DEPTH. Unresonably large depth causes memory allocation errors.
+// Uses multithreading. Makes one Branch from each top-level legal move and runs each in its own thread. 
+// 
+// Returns the index of the column whose branch has the highest score, the lowest such column if several branches
+// have the same score. The scores don't depend on the order the threads finish in, so the result is always the same.
+// Returns an error if it is not the protagonist's turn, if the depth is too small or if the grid is full.
+pub fn analyze_bfs_mt(grid: Grid, protagonist: Player, depth: u8) -> Result<usize, AnalysisError> {
+    analyze_bfs_mt_cancellable(grid, protagonist, depth, &Arc::new(AtomicBool::new(false)))
+}
+
+// Same as analyze_bfs_mt, but every thread stops searching when cancel is set, e.g. from another thread.
+// The branches are then compared by the positions they searched so far.
+pub fn analyze_bfs_mt_cancellable(grid: Grid, protagonist: Player, depth: u8, cancel: &Arc<AtomicBool>)
+                                  -> Result<usize, AnalysisError> {
+    
+    if grid.player_to_move() != protagonist {
+        return Err(AnalysisError::NotProtagonistsTurn { protagonist, to_move: grid.player_to_move() })
+    }
+    // Each Branch searches depth-1 moves and would never stop at a depth of 0
+    if depth < MIN_BFS_DEPTH {
+        return Err(AnalysisError::DepthTooSmall { depth, min: MIN_BFS_DEPTH })
+    }
+ 
+    let relevance = 1./grid.n_legal_f64();
+    
+    let mut handles = Vec::new();
+    
+    // Try all possible moves
+    for ([row, col], branch_grid) in grid.next_grids() {
+
+        // Overflows usize for wide grids and large depths, so the capacity is capped. The queue grows further if needed.
+        let queue_capacity = (grid.n_legal_f64() as usize).checked_pow((depth-1).into())
+            .unwrap_or(MAX_QUEUE_CAPACITY)
+            .min(MAX_QUEUE_CAPACITY);
+        
+        // Spawn one thread per 1st level branch
+        let cancel = Arc::clone(cancel);
+        handles.push(thread::spawn(move || {
+
+            match branch_grid.win_fast(row, col) {
+                0 => {                              // No one wins
+                    let mut branch = Branch::new(branch_grid, queue_capacity);
+                    branch.bfs(protagonist.number(), depth-1, &cancel);
+
+                    return (col, relevance*branch.score)
+                },
+                // Only the protagonist can win since it is the protagonist's move
+                // One loss has the same magnitude as legal_moves.len() wins  
+                _ => {                              // The protagonist wins
+                    return (col, relevance);       
+                },
+            }
+        }));
+    }
+    
+    // Ties go to the lowest column, not to whichever branch comes last in the center first order
+    let best_col = handles.into_iter()
+        .map(|handle| handle.join().unwrap())
+        .max_by(|(col_a, a), (col_b, b)| a.total_cmp(b).then(col_b.cmp(col_a)))
+        .map(|(col, _)| col);
+    
+    best_col.ok_or(AnalysisError::NoLegalMoves)
+    
+}
+
+
+// Directions of the lines a threat can be part of: '-', '/' and '\'.
+// Vertical threats are left out, since they are easy to block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ThreatShape {
+    Horizontal,
+    FwrdSlash,
+    BackSlash,
+}
+
+impl ThreatShape {
+    const ALL: [ThreatShape; 3] = [ThreatShape::Horizontal, ThreatShape::FwrdSlash, ThreatShape::BackSlash];
+}
+
+#[derive(Clone)]
+struct ThreatMap {
+    w: usize,
+    h: usize,
+    horizontal1: Vec<f64>,
+    horizontal2: Vec<f64>,
+    fwrd_slash1: Vec<f64>,
+    fwrd_slash2: Vec<f64>,
+    back_slash1: Vec<f64>,
+    back_slash2: Vec<f64>,
+}
+
+impl ThreatMap {
+    // Contains 6 grids that store the threat level of empty disc positions on the grid .
+    // The grids are indexed by threat shape: '-', '/', '\'; and player: 1, 2.
+    // All grid positions start with threat level 1.
+    // Non-empty grid positions have have threat level 0.
+
+    fn new(grid: &Grid) -> Self {
+        let w = grid.w;
+        let h = grid.h;
+        
+        ThreatMap { w, h, horizontal1: vec![1.; w*h], horizontal2: vec![1.; w*h],
+                          fwrd_slash1: vec![1.; w*h], fwrd_slash2: vec![1.; w*h],
+                          back_slash1: vec![1.; w*h], back_slash2: vec![1.; w*h]
+        }
+    }
+
+    // Threat map with all discs on the grid taken into account.
+    // Every disc is added with the whole grid in view, so lines that are blocked by a later disc don't count
+    // and the map only depends on the position, not on the order the discs were played in.
+    fn from_grid(grid: &Grid) -> Self {
+        let mut threat_map = ThreatMap::new(grid);
+        for i in 0..grid.h {
+            for j in 0..grid.w {
+                if grid.read(i, j) != 0 {
+                    threat_map.update_with(i, j, grid);
+                }
+            }
+        }
+        threat_map
+    }
+
+
+    // The layer of threat levels for one shape and player
+    fn layer(&self, threat_shape: ThreatShape, player: Player) -> &Vec<f64> {
+        match (threat_shape, player) {
+            (ThreatShape::Horizontal, Player::One) => &self.horizontal1,
+            (ThreatShape::Horizontal, Player::Two) => &self.horizontal2,
+            (ThreatShape::FwrdSlash, Player::One) => &self.fwrd_slash1,
+            (ThreatShape::FwrdSlash, Player::Two) => &self.fwrd_slash2,
+            (ThreatShape::BackSlash, Player::One) => &self.back_slash1,
+            (ThreatShape::BackSlash, Player::Two) => &self.back_slash2,
+        }
+    }
+    fn layer_mut(&mut self, threat_shape: ThreatShape, player: Player) -> &mut Vec<f64> {
+        match (threat_shape, player) {
+            (ThreatShape::Horizontal, Player::One) => &mut self.horizontal1,
+            (ThreatShape::Horizontal, Player::Two) => &mut self.horizontal2,
+            (ThreatShape::FwrdSlash, Player::One) => &mut self.fwrd_slash1,
+            (ThreatShape::FwrdSlash, Player::Two) => &mut self.fwrd_slash2,
+            (ThreatShape::BackSlash, Player::One) => &mut self.back_slash1,
+            (ThreatShape::BackSlash, Player::Two) => &mut self.back_slash2,
+        }
+    }
+
+    fn read(&self, i: usize, j: usize, threat_shape: ThreatShape, player: Player) -> f64 {
+        self.layer(threat_shape, player)[i*self.w + j]
+    }
+
+    // increment and nullify ignore positions outside the grid, so that callers don't have to check the walls
+    fn increment(&mut self, i: usize, j: usize, threat_shape: ThreatShape, player: Player) {
+        if (0..self.h).contains(&i) && (0..self.w).contains(&j) {
+            let w = self.w;
+            self.layer_mut(threat_shape, player)[i*w + j] += 1.;
+        }
+    }
+    fn nullify(&mut self, i: usize, j: usize, threat_shape: ThreatShape, player: Player) {
+        if (0..self.h).contains(&i) && (0..self.w).contains(&j) {
+            let w = self.w;
+            self.layer_mut(threat_shape, player)[i*w + j] = 0.;
+        }
+    }
+
+    // Update the threatmap with the disc in (row,col)
+    // (row,col) has to hold a disc. Grid cells only ever hold 0, 1 or 2,
+    // so a non-empty cell that is not the player's holds an enemy disc.
+    fn update_with(&mut self, row: usize, col: usize, grid: &Grid) {
+        let Some(player) = Player::from_number(grid.read(row, col)) else {
+            debug_assert!(false, "update_with is only called for a position with a disc");
+            return
+        };
+        
         // Horizontal: -
-        self.nullify(row, col, '-', player);
+        self.nullify(row, col, ThreatShape::Horizontal, player);
 
         let mut enclosure_extent_right = 0;
         for k in 1..=grid.l {                               // Rightward
@@ -525,14 +1457,13 @@ impl ThreatMap {
             match grid.read(row,col+k) {
                 0 => {
                     if k != grid.l {
-                        self.increment(row, col+k, '-', player);
+                        self.increment(row, col+k, ThreatShape::Horizontal, player);
                     }
                 },
-                disc if disc == player => {
+                disc if disc == player.number() => {
                     enclosure_extent_right = k-1
                 },
-                disc if disc == 3-player => break,
-                _ => panic!()
+                _ => break                                  // Enemy disc
             }
         }
         let mut enclosure_extent_left = 0;
@@ -544,25 +1475,24 @@ impl ThreatMap {
             match grid.read(row,col-k) {
                 0 => {
                     if k != grid.l {
-                        self.increment(row, col-k, '-', player);
+                        self.increment(row, col-k, ThreatShape::Horizontal, player);
                     }
                 },
-                disc if disc == player => {
+                disc if disc == player.number() => {
                     enclosure_extent_left = k-1
                 },
-                disc if disc == 3-player => break,
-                _ => panic!()
+                _ => break                                  // Enemy disc
             } 
         }
         // Nullify enclosed disc positions for the enemy
         for j in col-enclosure_extent_left..=col+enclosure_extent_right {
-            self.nullify(row, j, '-', 3-player);
+            self.nullify(row, j, ThreatShape::Horizontal, player.other());
         }
         
 
         // Diagonal: /
-        self.nullify(row, col, '/', 1);
-        self.nullify(row, col, '/', 2);
+        self.nullify(row, col, ThreatShape::FwrdSlash, Player::One);
+        self.nullify(row, col, ThreatShape::FwrdSlash, Player::Two);
 
         let mut enclosure_extent_up_right = 0;
         for k in 1..=grid.l {                               // Up-rightward
@@ -573,23 +1503,22 @@ impl ThreatMap {
             match grid.read(row+k,col+k) {
                 0 => {
                     if k != grid.l {
-                        self.increment(row+k, col+k, '/', player);
+                        self.increment(row+k, col+k, ThreatShape::FwrdSlash, player);
                     }
                 },
-                disc if disc == player => {
+                disc if disc == player.number() => {
                     enclosure_extent_up_right = k-1
                 },
-                disc if disc == 3-player => break,
-                _ => panic!()
+                _ => break                                  // Enemy disc
             }
         }
         // Nullify enclosed disc positions for the enemy
         for k in 1..=enclosure_extent_up_right {
-            self.nullify(row+k, col+k, '/', 3-player);
+            self.nullify(row+k, col+k, ThreatShape::FwrdSlash, player.other());
         }
 
         let mut enclosure_extent_down_left = 0;
-        for k in 1..=(row+1).min(col+1) {                   // Down-leftward
+        for k in 1..=grid.l {                               // Down-leftward
             if k == (row+1).min(col+1) {
                 enclosure_extent_down_left = k-1;
                 break;                                  // Stops if it hits the wall
@@ -597,25 +1526,24 @@ impl ThreatMap {
             match grid.read(row-k,col-k) {
                 0 => {
                     if k != grid.l {
-                        self.increment(row-k, col-k, '/', player);
+                        self.increment(row-k, col-k, ThreatShape::FwrdSlash, player);
                     }
                 },
-                disc if disc == player => {
+                disc if disc == player.number() => {
                     enclosure_extent_down_left = k-1
                 },
-                disc if disc == 3-player => break,
-                _ => panic!()
+                _ => break                                  // Enemy disc
             }
         }
         // Nullify enclosed disc positions for the enemy
         for k in 1..=enclosure_extent_down_left {
-            self.nullify(row-k, col-k, '/', 3-player);
+            self.nullify(row-k, col-k, ThreatShape::FwrdSlash, player.other());
         }
         
         
         // Diagonal: \
-        self.nullify(row, col, '\\', 1);
-        self.nullify(row, col, '\\', 2);
+        self.nullify(row, col, ThreatShape::BackSlash, Player::One);
+        self.nullify(row, col, ThreatShape::BackSlash, Player::Two);
 
         let mut enclosure_extent_up_left = 0;
         for k in 1..=grid.l {                               // Up-leftward
@@ -626,19 +1554,18 @@ impl ThreatMap {
             match grid.read(row+k,col-k) {   
                 0 => {
                     if k != grid.l {
-                        self.increment(row+k, col-k, '\\', player);
+                        self.increment(row+k, col-k, ThreatShape::BackSlash, player);
                     }
                 },
-                disc if disc == player => {
+                disc if disc == player.number() => {
                     enclosure_extent_up_left = k-1
                 },
-                disc if disc == 3-player => break,
-                _ => panic!()
+                _ => break                                  // Enemy disc
             } 
         }
         // Nullify enclosed disc positions for the enemy
         for k in 1..=enclosure_extent_up_left {
-            self.nullify(row+k, col-k, '\\', 3-player);
+            self.nullify(row+k, col-k, ThreatShape::BackSlash, player.other());
         }
 
         let mut enclosure_extent_down_right = 0;
@@ -650,91 +1577,245 @@ impl ThreatMap {
             match grid.read(row-k,col+k) {
                 0 => {
                     if k != grid.l {
-                        self.increment(row-k, col+k, '\\', player);
+                        self.increment(row-k, col+k, ThreatShape::BackSlash, player);
                     }
                 },
-                disc if disc == player => {
+                disc if disc == player.number() => {
                     enclosure_extent_down_right = k-1
                 },
-                disc if disc == 3-player => break,
-                _ => panic!()
+                _ => break                                  // Enemy disc
             }
         }
         // Nullify enclosed disc positions for the enemy
         for k in 1..=enclosure_extent_down_right {
-            self.nullify(row+k, col+k, '\\', 3-player);
+            self.nullify(row-k, col+k, ThreatShape::BackSlash, player.other());
         }
     }
 }
 
 
 
+// Value of a won position in the minmax search. Lost positions have the value -WIN_VALUE.
+// The depth that was left to search is added to the magnitude, so that faster wins and slower losses are preferred.
+pub const WIN_VALUE: f64 = 3e6;
+
+// Moves a value that was searched with stored_depth moves left to a search with depth moves left.
+// Win and loss values hold the depth that was left when the game ended, see WIN_VALUE, so they are lowered
+// by the difference. A game that ends beyond the depth of this search counts as ending at its horizon.
+fn rebase_value(value: f64, stored_depth: u8, depth: u8) -> f64 {
+    if value.abs() < WIN_VALUE {
+        return value
+    }
+    let depth_left = (value.abs() - WIN_VALUE - (stored_depth - depth) as f64).max(0.);
+    value.signum()*(WIN_VALUE + depth_left)
+}
+
+// Number of moves, counting both players and starting with the analyzed move, until the game ends in the position
+// that gave the value. Only works with values from a search of the given depth from the root.
+// Gives None if the value is not a forced win or loss.
+pub fn moves_to_end(value: f64, depth: u8) -> Option<u8> {
+    if value.abs() < WIN_VALUE {
+        return None
+    }
+    let depth_left = (value.abs() - WIN_VALUE) as u8;
+    Some(depth.max(1) - depth_left)
+}
+
+// Weights of optional terms in the heuristic. The default weights are 0, which leaves the heuristic unchanged.
+// 
+// Two terms look at the position below every threat (empty position where a disc would complete a line).
+// They are given for the protagonist and subtracted for the enemy:
+//      unfilled_threat_bonus is added for every threat with an empty position below it, since
+//          the threat can't be blocked yet.
+//      self_block_penalty is subtracted for every threat with an own disc directly below it, since
+//          filling below an own threat lets the enemy block it right away.
+// The other terms are one-sided:
+//      threat_bonus is added for every threat of the protagonist.
+//      enemy_threat_penalty is subtracted for every threat of the enemy.
+//      center_bonus is added for every disc of the protagonist in the center column(s) and subtracted for the enemy's.
+// Drawn games have the value 0 unless draw_favored is set. Then a draw is worth draw_bonus to that player
+// and -draw_bonus to the other, for example when the second player counts a draw as a success.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeuristicParams {
+    pub unfilled_threat_bonus: f64,
+    pub self_block_penalty: f64,
+    pub threat_bonus: f64,
+    pub enemy_threat_penalty: f64,
+    pub center_bonus: f64,
+    pub draw_favored: Option<Player>,
+    pub draw_bonus: f64,
+}
+
+impl HeuristicParams {
+    // Value of a drawn game for the protagonist
+    fn draw_value(&self, protagonist: Player) -> f64 {
+        match self.draw_favored {
+            Some(player) if player == protagonist => self.draw_bonus,
+            Some(_) => -self.draw_bonus,
+            None => 0.
+        }
+    }
+
+    // Value of the center_bonus term for the protagonist
+    fn center_score(&self, grid: &Grid, protagonist: Player) -> f64 {
+        self.center_bonus*(grid.center_discs(protagonist.number()) as f64 - grid.center_discs(protagonist.other().number()) as f64)
+    }
+
+    // Value of the optional terms that depend on threats for the protagonist
+    fn threat_score(&self, grid: &Grid, protagonist: Player) -> f64 {
+        let mut score = 0.;
+        for player in [1, 2] {
+            let (sign, threat_weight) = match player {
+                p if p == protagonist.number() => (1., self.threat_bonus),
+                _ => (-1., self.enemy_threat_penalty)
+            };
+            for (i, j) in grid.threat_cells(player) {
+                score += sign*threat_weight;
+                if i == 0 {
+                    continue
+                }
+                match grid.read(i-1, j) {
+                    0 => score += sign*self.unfilled_threat_bonus,
+                    below if below == player => score -= sign*self.self_block_penalty,
+                    _ => ()
+                }
+            }
+        }
+        score
+    }
+}
+
+// Named presets of HeuristicParams that give the computer different playing styles
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Persona {
+    Aggressive,     // Goes for its own threats
+    Positional,     // Plays for the center and threats that can't be blocked yet
+    Defensive,      // Keeps the enemy's threats down
+}
+
+impl Persona {
+    pub const ALL: [Persona; 3] = [Persona::Aggressive, Persona::Positional, Persona::Defensive];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Persona::Aggressive => "aggressive",
+            Persona::Positional => "positional",
+            Persona::Defensive => "defensive",
+        }
+    }
+
+    pub fn params(&self) -> HeuristicParams {
+        match self {
+            Persona::Aggressive => HeuristicParams { threat_bonus: 4., enemy_threat_penalty: 0.5, ..Default::default() },
+            Persona::Positional => HeuristicParams { unfilled_threat_bonus: 1., self_block_penalty: 2., center_bonus: 1.,
+                                                     ..Default::default() },
+            Persona::Defensive => HeuristicParams { threat_bonus: 0.5, enemy_threat_penalty: 4., ..Default::default() },
+        }
+    }
+}
+
+impl FromStr for Persona {
+    type Err = String;
+
+    // Finds a persona by its name, ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Persona::ALL.into_iter()
+            .find(|persona| persona.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or(format!("unknown persona \"{}\"", s.trim()))
+    }
+}
+
 #[derive(Clone)]
 pub struct Node {
     // Structure used for minmax exploration
     grid: Grid,
-    threat_map: ThreatMap,
+    params: HeuristicParams,
 }
 
 impl Node {
     fn new(grid: Grid) -> Self {
-        let threat_map = ThreatMap::new(&grid);
-        
-        Node {grid, threat_map}
+        Node::with_params(grid, HeuristicParams::default())
     }
 
-    fn create_children(&self) -> HashMap<[usize; 2], Node> {
-        // Gives new nodes for all possible grid states that can be reached with one move 
-        let legal_moves = self.grid.legal_moves();
-        let mut children = HashMap::with_capacity(legal_moves.len());
+    fn with_params(grid: Grid, params: HeuristicParams) -> Self {
+        Node {grid, params}
+    }
+
+    fn create_children(&self) -> Vec<([usize; 2], Node)> {
+        // Gives new nodes for all possible grid states that can be reached with one move, in center first order
+        let legal_moves = self.grid.center_first_moves();
+        let mut children = Vec::with_capacity(legal_moves.len());
         
         for col in legal_moves {
             let mut child = self.clone();
             let row = child.grid.play(col);
-            children.insert([row, col], child);
+            children.push(([row, col], child));
         }
         children
     }
 
-    
-    
-    fn update_threat_map(&mut self, row: usize, col: usize) {
-        self.threat_map.update_with(row, col, &self.grid);
-    }
     // Horizontal and diagonal threats on rows with prefered parity.
     // Player 1 wants threats in even rows, player 2 in odd rows. The bottom row is row 0.
-    // Only works if self.h is even.
-    fn heuristic(&self, protagonist: u8) -> f64 {
-        if self.grid.h%2 == 1 {
-            panic!();
-        }
+    // Odd heights have no parity, see row_score.
+    // The threat map is built from the position, so the value is the same however the position was reached.
+    fn heuristic(&self, protagonist: Player) -> f64 {
+        let threat_map = ThreatMap::from_grid(&self.grid);
         let mut score = 0.;
 
         for i in 0..self.grid.h {
+            score += self.row_score(&threat_map, i, protagonist)
+        }
+
+        // Finding the threats is slow, so it is only done when the optional terms are used
+        if self.params != HeuristicParams::default() {
+            score += self.params.center_score(&self.grid, protagonist);
+            score += self.params.threat_score(&self.grid, protagonist);
+        }
+        
+        return score
+    }
+
+    // Contribution of the threats on row i to the heuristic.
+    // On even heights only the threats with the right parity count: the first player's on even i and
+    // the second player's on odd i. Odd heights have no such parity, so every threat counts for its owner.
+    fn row_score(&self, threat_map: &ThreatMap, i: usize, protagonist: Player) -> f64 {
+        if self.grid.h%2 == 1 {
+            let mut row_score = 0.;
+            for player in [Player::One, Player::Two] {
+                let sign = if player == protagonist {1.} else {-1.};
+                for j in 0..self.grid.w {
+                    for threat_shape in ThreatShape::ALL {
+                        row_score += sign*threat_map.read(i, j, threat_shape, player).powi(2)
+                    }
+                }
+            }
+            return row_score/(1.+i as f64)
+        }
 
-            let correct_parity_disc = (i%2 + 1) as u8;
+        let correct_parity_disc = match i%2 {
+            0 => Player::One,
+            _ => Player::Two
+        };
 
-            let sign = match correct_parity_disc {
-                player if player == protagonist => 1.,
-                _ => -1.
-            };
+        let sign = match correct_parity_disc {
+            player if player == protagonist => 1.,
+            _ => -1.
+        };
 
-            let mut row_score = 0.;
-            
-            for j in 0..self.grid.w {
-                for threat_shape in ['-', '/', '\\'] {
-                    row_score += self.threat_map.read(i, j, threat_shape, correct_parity_disc).powi(2)
-                }
+        let mut row_score = 0.;
+        
+        for j in 0..self.grid.w {
+            for threat_shape in ThreatShape::ALL {
+                row_score += threat_map.read(i, j, threat_shape, correct_parity_disc).powi(2)
             }
-            
-            // Lower threats are worth more 
-            score += sign*row_score/(1.+i as f64)
         }
         
-        return score
+        // Lower threats are worth more 
+        return sign*row_score/(1.+i as f64)
     }
-    fn get_value_alpha_beta(&mut self, depth: u8, protagonist: u8, row: usize, col: usize, 
-                            parent_alpha: f64, parent_beta: f64, transp_table: &mut HashMap<u64, (f64, i8)>) -> f64 {
+    fn get_value_alpha_beta(&mut self, depth: u8, protagonist: Player, row: usize, col: usize, 
+                            parent_alpha: f64, parent_beta: f64, transp_table: &mut HashMap<u64, (f64, i8, u8)>,
+                            capacity: usize, stats: &mut SearchStats) -> f64 {
         // Get the value of this node from the values of its children recursively
         // 
         // protagonist denotes wich player the analysis is done for
@@ -745,28 +1826,45 @@ impl Node {
         //      0 means exact value,
         //      -1 means alpha value,
         //      +1 means beta value.
+        // Third tuple entry is the depth that was left to search from the position.
+        // The table is cleared when it holds capacity entries and a new position has to be stored.
         
         let mut alpha = parent_alpha;
         let mut beta = parent_beta;
+        stats.nodes += 1;
+        if stats.nodes.is_multiple_of(PROGRESS_INTERVAL) {
+            stats.report_progress();
+        }
+
+        // The result of a stopped search is thrown away, so any value will do
+        if stats.should_stop() {
+            return 0.
+        }
 
         // Get cached value if this state has been seen before.
-        let state_id = calculate_hash(&self.grid);
-        match transp_table.get(&state_id) {
-            Some((stored_value, stored_type))  => {
+        // Values that were searched with fewer moves left than here are not used, since they may be wrong at this depth.
+        // Mirror images share an entry, so a position is also found if only its mirror image was searched.
+        let state_id = self.grid.canonical_hash();
+        if let Some(&(stored_value, stored_type, stored_depth)) = transp_table.get(&state_id) {
+            if stored_depth >= depth {
+                let stored_value = rebase_value(stored_value, stored_depth, depth);
                 match stored_type {
-                    -1 => alpha = alpha.max(*stored_value),         // Alpha value
-                    0 => return *stored_value,                      // Exact value
-                    1 => beta = beta.max(*stored_value),            // Beta value
-                    _ => panic!()
+                    -1 => alpha = alpha.max(stored_value),          // Alpha value, a lower bound
+                    0 => {                                          // Exact value
+                        stats.transpositions.insert(state_id);
+                        return stored_value
+                    },
+                    _ => beta = beta.min(stored_value),             // Beta value, an upper bound
                 }
                 if alpha >= beta {
-                    return *stored_value
+                    return stored_value
                 }
-            },
-            _ => ()
+            }
         }
 
-        let mut value_type = 0;
+        // Window of this node. A value outside of it is only a bound, since some children were not searched
+        // or were searched with a window that cut their value off.
+        let (window_alpha, window_beta) = (alpha, beta);
 
         let mut value: f64;
         match self.grid.win_fast(row, col) {
@@ -777,25 +1875,28 @@ impl Node {
                 }      
 
                 else if self.grid.n_legal_f64() == 0. {             // Game over (draw)
-                    value = 0.
+                    value = self.params.draw_value(protagonist)
                 }
                 
                 else if self.grid.player_to_move() == protagonist {      // The protagonist's turn
                     
                     value = f64::NEG_INFINITY;                             
                     
-                    // Clone the grid and try all possible moves.
-                    for ([row, col], mut child) in self.create_children(){
-                        child.update_threat_map(row, col);
-                        let child_value = child.get_value_alpha_beta(depth-1, protagonist, row, col, 
-                                                                          alpha, beta, transp_table);
+                    // Try all possible moves on this grid and take them back, the ones that caused cutoffs elsewhere first.
+                    let mut moves = self.grid.center_first_moves();
+                    stats.order_moves(&mut moves, depth);
+                    for col in moves {
+                        let row = self.grid.play(col);
+                        let child_value = self.get_value_alpha_beta(depth-1, protagonist, row, col, 
+                                                                    alpha, beta, transp_table, capacity, stats);
+                        self.grid.unplay(col);
                         
                         // Keep the maximal value
                         value = value.max(child_value);
                         alpha = alpha.max(value);
 
                         if beta <= alpha {
-                            value_type = -1;                // Store value type: alpha
+                            stats.record_cutoff(depth, col);
                             break                           // Beta prune
                         }
                     }
@@ -803,60 +1904,499 @@ impl Node {
                 else {                                                  // The other player's turn
                     value = f64::INFINITY;
 
-                    // Clone the grid and try all possible moves.
-                    for ([row, col], mut child) in self.create_children() {
-                        child.update_threat_map(row, col);
-                        let child_value = child.get_value_alpha_beta(depth-1, protagonist, row, col, 
-                                                                          alpha, beta, transp_table);
+                    // Try all possible moves on this grid and take them back, the ones that caused cutoffs elsewhere first.
+                    let mut moves = self.grid.center_first_moves();
+                    stats.order_moves(&mut moves, depth);
+                    for col in moves {
+                        let row = self.grid.play(col);
+                        let child_value = self.get_value_alpha_beta(depth-1, protagonist, row, col, 
+                                                                    alpha, beta, transp_table, capacity, stats);
+                        self.grid.unplay(col);
                         
                         // Keep the minimal value
                         value = value.min(child_value);
                         beta = beta.min(value);
 
                         if beta <= alpha {
-                            value_type = 1;                 // Store value type: beta
+                            stats.record_cutoff(depth, col);
                             break                           // Alpha prune
                         }
                     }
                 }
             },
-            w if w == protagonist => {          // The protagonist wins
-                value = 3e6;
+            w if w == protagonist.number() => {          // The protagonist wins
+                value = WIN_VALUE + depth as f64;
             },         
             _ => {                                  // The other player wins
-                value = -3e6;
+                value = -(WIN_VALUE + depth as f64);
             }                                  
         }
 
-        transp_table.insert(state_id, (value, value_type));
+        // Values from an interrupted search are not stored, since they are wrong
+        if stats.stopped {
+            return value
+        }
+        let value_type = if value >= window_beta {
+            -1                                      // Store value type: alpha
+        } else if value <= window_alpha {
+            1                                       // Store value type: beta
+        } else {
+            0
+        };
+        if transp_table.len() >= capacity && !transp_table.contains_key(&state_id) {
+            transp_table.clear();
+        }
+        transp_table.insert(state_id, (value, value_type, depth));
         return value
     }
 }
 
-pub fn analyze_alphabeta(grid: Grid, protagonist: u8, depth: u8) -> (usize, f64) {
+// Searches the move tree to the given depth with alpha-beta pruning. A depth of 0 is treated as 1.
+pub fn analyze_alphabeta(grid: Grid, protagonist: Player, depth: u8) -> (usize, f64) {
     // Will play the move with the highest value 
 
-    let mut best_col = grid.width();  // This is an illegal move but should always be overridden.
+    let mut transp_table: HashMap<u64, (f64, i8, u8)> = HashMap::new();
     
-    let root_node = Node::new(grid);
+    search_root(grid, protagonist, depth, DEFAULT_EPSILON, &[], HeuristicParams::default(), &mut transp_table,
+                DEFAULT_TABLE_CAPACITY, &mut SearchStats::default())
+}
+
+// Same as analyze_alphabeta, but checks the input first instead of giving a meaningless result:
+// it has to be the protagonist's turn and the grid can't be full.
+pub fn try_analyze_alphabeta(grid: Grid, protagonist: Player, depth: u8) -> Result<(usize, f64), AnalysisError> {
+    if grid.player_to_move() != protagonist {
+        return Err(AnalysisError::NotProtagonistsTurn { protagonist, to_move: grid.player_to_move() })
+    }
+    if grid.legal_moves().is_empty() {
+        return Err(AnalysisError::NoLegalMoves)
+    }
+    Ok(analyze_alphabeta(grid, protagonist, depth))
+}
+
+// Number of visited positions between two reports to the callback of analyze_with_progress
+pub const PROGRESS_INTERVAL: u64 = 1 << 16;
+
+// How far a running alpha-beta search has come, see analyze_with_progress
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchProgress {
+    pub nodes: u64,             // Number of positions visited so far
+    pub root_move: usize,       // Index of the root move that is being searched, counted in the order they are searched
+    pub root_moves: usize,      // Number of root moves
+}
+
+// Callback that is given the progress of a search
+#[derive(Clone)]
+struct ProgressCallback(Arc<dyn Fn(SearchProgress) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ProgressCallback")
+    }
+}
+
+// Counters collected during an alpha-beta search
+#[derive(Clone, Debug, Default)]
+pub struct SearchStats {
+    pub nodes: u64,                 // Number of positions visited, including the ones found in the transposition table
+    transpositions: HashSet<u64>,   // Positions whose exact value was taken from the transposition table
+    deadline: Option<Instant>,      // The search is stopped at this time
+    cancel: Option<Arc<AtomicBool>>,    // The search is stopped when this is set
+    stopped: bool,
+    progress: Option<ProgressCallback>, // Called every PROGRESS_INTERVAL nodes and at the start of every root move
+    root_move: usize,
+    root_moves: usize,
+    killers: Vec<[Option<usize>; 2]>,   // Per depth left: the last two columns that caused a cutoff at that depth
+    history: Vec<u64>,                  // Per column: how often a move in the column caused a cutoff, weighted by depth
+}
+
+impl SearchStats {
+    // Number of distinct positions that were reached through more than one sequence of moves.
+    // Only positions whose exact value was found in the transposition table and used instead of searching them
+    // again are counted. Mirror images share an entry, so a position also counts when its mirror image was searched.
+    pub fn transpositions(&self) -> usize {
+        self.transpositions.len()
+    }
+
+    // Checks if the search ran past its deadline or was cancelled.
+    // The clock and the flag are only read every 1024 nodes, since reading the clock is slow.
+    fn should_stop(&mut self) -> bool {
+        if !self.stopped && self.nodes.is_multiple_of(1024) {
+            self.check_stop();
+        }
+        self.stopped
+    }
+
+    // Reads the clock and the cancel flag to see if the search has to stop
+    fn check_stop(&mut self) -> bool {
+        let cancelled = self.cancel.as_ref().is_some_and(|cancel| cancel.load(atomic::Ordering::Relaxed));
+        self.stopped = cancelled || self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        self.stopped
+    }
+
+    // Sorts the moves so that the killer moves of this depth come first, most recent first,
+    // followed by the other moves with the highest history score first.
+    // The sort is stable, so moves that are equal on both counts stay center first.
+    fn order_moves(&self, moves: &mut [usize], depth: u8) {
+        let killers = self.killers.get(depth as usize).copied().unwrap_or_default();
+        moves.sort_by_key(|&col| {
+            let killer_rank = killers.iter().position(|&killer| killer == Some(col)).unwrap_or(killers.len());
+            (killer_rank, std::cmp::Reverse(self.history.get(col).copied().unwrap_or(0)))
+        });
+    }
+
+    // Remembers that a move in col caused a cutoff with depth moves left to search.
+    // Cutoffs close to the root prune larger subtrees, so they add more to the history score.
+    fn record_cutoff(&mut self, depth: u8, col: usize) {
+        let depth = depth as usize;
+        if self.killers.len() <= depth {
+            self.killers.resize(depth + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[depth];
+        if killers[0] != Some(col) {
+            killers[1] = killers[0];
+            killers[0] = Some(col);
+        }
+
+        if self.history.len() <= col {
+            self.history.resize(col + 1, 0);
+        }
+        self.history[col] += (depth*depth) as u64;
+    }
+
+    // Gives the progress to the callback, if there is one
+    fn report_progress(&self) {
+        if let Some(ProgressCallback(callback)) = &self.progress {
+            callback(SearchProgress { nodes: self.nodes, root_move: self.root_move, root_moves: self.root_moves });
+        }
+    }
+}
+
+// Same as analyze_alphabeta, but also gives the statistics of the search
+pub fn analyze_with_stats(grid: Grid, protagonist: Player, depth: u8) -> (usize, f64, SearchStats) {
+    let mut transp_table: HashMap<u64, (f64, i8, u8)> = HashMap::new();
+    let mut stats = SearchStats::default();
+
+    let (col, value) = search_root(grid, protagonist, depth, DEFAULT_EPSILON, &[], HeuristicParams::default(),
+                                   &mut transp_table, DEFAULT_TABLE_CAPACITY, &mut stats);
+    (col, value, stats)
+}
+
+// Same as analyze_with_stats, but the progress of the search is given to the callback while it runs,
+// e.g. to show that the computer is still thinking. The callback is called at the start of every root move
+// and every PROGRESS_INTERVAL visited positions.
+pub fn analyze_with_progress(grid: Grid, protagonist: Player, depth: u8, progress: impl Fn(SearchProgress) + Send + Sync + 'static)
+                             -> (usize, f64, SearchStats) {
+    let mut transp_table: HashMap<u64, (f64, i8, u8)> = HashMap::new();
+    let mut stats = SearchStats { progress: Some(ProgressCallback(Arc::new(progress))), ..Default::default() };
+
+    let (col, value) = search_root(grid, protagonist, depth, DEFAULT_EPSILON, &[], HeuristicParams::default(),
+                                   &mut transp_table, DEFAULT_TABLE_CAPACITY, &mut stats);
+    (col, value, stats)
+}
+
+// Same as analyze_alphabeta, but also gives the principal variation: the sequence of moves, starting with the best
+// move, that the search expects when both players play their best moves. The variation has at most depth moves and
+// ends early if the game is decided.
+// 
+// Each move after the first is found by searching the position it is played in again, for the player to move and
+// with the depth that is left. Minimax values don't depend on whose perspective they are computed from, so this gives
+// a line with the same value as the original search. With equally good replies, it may be another line than the
+// one the search saw first.
+pub fn analyze_alphabeta_pv(grid: Grid, protagonist: Player, depth: u8) -> (Vec<usize>, f64) {
+    let depth = depth.max(1);       // Same as in search_root
+    let (col, value) = analyze_alphabeta(grid.clone(), protagonist, depth);
+
+    let mut grid = grid;
+    let mut pv = Vec::with_capacity(depth as usize);
+    let mut next_col = col;
+    for remaining in (0..depth).rev() {
+        if !grid.can_play(next_col) {
+            break
+        }
+        let row = grid.play(next_col);
+        pv.push(next_col);
+        if remaining == 0 || grid.win_fast(row, next_col) != 0 {
+            break
+        }
+        next_col = analyze_alphabeta(grid.clone(), grid.player_to_move(), remaining).0;
+    }
+    (pv, value)
+}
+
+// Searches with increasing depth until the time budget is used up, starting at depth 2.
+// Returns the best move and its value from the deepest search that was completed, together with that depth.
+// A search that runs out of time is stopped and its result thrown away. If not even depth 2 could be completed,
+// the result of depth 1 is given. The deepening also stops once the value is a forced win or loss or the
+// search reaches the end of the game.
+// 
+// All depths share one transposition table. Stored values carry the depth that was left to search,
+// so an entry is only used where it was searched at least as deep.
+pub fn analyze_alphabeta_timed(grid: Grid, protagonist: Player, time_budget: Duration) -> (usize, f64, u8) {
+    let max_depth = grid.moves_remaining().min(u8::MAX as usize) as u8;
+    let stats = SearchStats { deadline: Some(Instant::now() + time_budget), ..Default::default() };
+    deepen(grid, protagonist, max_depth, stats)
+}
+
+// Same as analyze_alphabeta, but the search can be stopped by setting cancel, e.g. from another thread.
+// The search is deepened one move at a time up to the given depth, and a stopped search gives
+// the best move of the deepest search that was finished, together with its value and depth.
+// Depth 1 is always searched to the end, so that there is a move to give.
+pub fn analyze_alphabeta_cancellable(grid: Grid, protagonist: Player, depth: u8, cancel: &Arc<AtomicBool>) -> (usize, f64, u8) {
+    let stats = SearchStats { cancel: Some(Arc::clone(cancel)), ..Default::default() };
+    deepen(grid, protagonist, depth, stats)
+}
+
+// Iterative deepening for the timed and the cancellable search. Searches depth 1 without stopping and then
+// depths 2..=max_depth, all with the same transposition table and with the deadline and cancel flag of stats.
+// Deepening ends when a search is stopped or a win or loss is found.
+fn deepen(grid: Grid, protagonist: Player, max_depth: u8, mut stats: SearchStats) -> (usize, f64, u8) {
+    let (col, value) = analyze_alphabeta(grid.clone(), protagonist, 1);
+    let mut best = (col, value, 1);
+
+    let mut transp_table: HashMap<u64, (f64, i8, u8)> = HashMap::new();
+    for depth in 2..=max_depth {
+        if best.1.abs() >= WIN_VALUE || stats.check_stop() {
+            break
+        }
+        let (col, value) = search_root(grid.clone(), protagonist, depth, DEFAULT_EPSILON, &[], HeuristicParams::default(),
+                                       &mut transp_table, DEFAULT_TABLE_CAPACITY, &mut stats);
+        if stats.stopped {
+            break
+        }
+        best = (col, value, depth);
+    }
+    best
+}
+
+// Same as analyze_alphabeta, but with the heuristic of the given persona
+pub fn analyze_persona(grid: Grid, protagonist: Player, depth: u8, persona: Persona) -> (usize, f64) {
+    let mut transp_table: HashMap<u64, (f64, i8, u8)> = HashMap::new();
+
+    search_root(grid, protagonist, depth, DEFAULT_EPSILON, &[], persona.params(), &mut transp_table,
+                DEFAULT_TABLE_CAPACITY, &mut SearchStats::default())
+}
+
+// Same as analyze_alphabeta, but the forbidden columns are not played at the root, e.g. for handicaps or variants
+// with disabled columns. The enemy may still play in them in the search.
+// Returns grid.width() (an illegal move) if every legal move is forbidden.
+pub fn analyze_restricted(grid: Grid, protagonist: Player, depth: u8, forbidden: &[usize]) -> (usize, f64) {
+    let mut transp_table: HashMap<u64, (f64, i8, u8)> = HashMap::new();
+    
+    search_root(grid, protagonist, depth, DEFAULT_EPSILON, forbidden, HeuristicParams::default(), &mut transp_table,
+                DEFAULT_TABLE_CAPACITY, &mut SearchStats::default())
+}
+
+// Same as analyze_alphabeta, but the transposition table is filled with known exact values before the search.
+// The pretable maps canonical position hashes (see Grid::canonical_hash) to values for the protagonist, 
+// e.g. from a previous deeper search. Any position after the root that is found in the pretable is not searched
+// further. The root itself is always searched, since a value alone doesn't tell which move to play.
+// Pretable entries are stored with depth u8::MAX, since the depth they were searched to is not known.
+// The table size is not bounded, since clearing a full table would also drop the pretable.
+pub fn analyze_with_pretable(grid: Grid, protagonist: Player, depth: u8, pretable: &HashMap<u64, f64>) -> (usize, f64) {
+    let mut transp_table: HashMap<u64, (f64, i8, u8)> = pretable.iter()
+        .map(|(&state_id, &value)| (state_id, (value, 0, u8::MAX)))
+        .collect();
+
+    search_root(grid, protagonist, depth, DEFAULT_EPSILON, &[], HeuristicParams::default(), &mut transp_table,
+                usize::MAX, &mut SearchStats::default())
+}
+
+// Reads a pretable for analyze_with_pretable from a text file. 
+// Every line holds a position hash and its value separated by whitespace. Lines starting with '#' are ignored.
+pub fn read_pretable(path: impl AsRef<Path>) -> io::Result<HashMap<u64, f64>> {
+    let mut pretable = HashMap::new();
+
+    for line in fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+
+        let mut fields = line.split_whitespace();
+        match (fields.next().map(str::parse::<u64>), fields.next().map(str::parse::<f64>)) {
+            (Some(Ok(state_id)), Some(Ok(value))) => {
+                pretable.insert(state_id, value);
+            },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid pretable line \"{line}\"")))
+        }
+    }
+    Ok(pretable)
+}
+
+// Maximal number of positions in the transposition table of an alpha-beta search, unless set otherwise
+pub const DEFAULT_TABLE_CAPACITY: usize = 1 << 22;
+
+// Transposition table that can be kept from one analysis to the next, e.g. for all moves of a game,
+// see analyze_alphabeta_with_table.
+// 
+// Entries are keyed on the position alone and store the number of moves that were left to search from it.
+// A stored value is only used where at most that many moves are left to search, so entries from earlier
+// analyses with another root or depth stay valid. The values are for one protagonist, so the table is
+// cleared when it is used for the other player.
+#[derive(Clone, Debug)]
+pub struct TranspositionTable {
+    entries: HashMap<u64, (f64, i8, u8)>,   // Position hash -> (value, value type, depth left)
+    protagonist: Option<Player>,            // The player the values are for
+    capacity: usize,                        // Maximal number of stored positions
+}
+
+impl TranspositionTable {
+    pub fn new() -> Self {
+        TranspositionTable::with_capacity(DEFAULT_TABLE_CAPACITY)
+    }
+
+    // Table that holds at most capacity positions. A full table is cleared when a new position has to be stored,
+    // which keeps the memory use bounded at the cost of searching some positions again.
+    pub fn with_capacity(capacity: usize) -> Self {
+        TranspositionTable { entries: HashMap::new(), protagonist: None, capacity: capacity.max(1) }
+    }
+
+    // Number of stored positions
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Forgets all stored positions
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.protagonist = None;
+    }
+
+    // Clears the table if its values are for the other player
+    fn prepare(&mut self, protagonist: Player) {
+        if self.protagonist != Some(protagonist) {
+            self.entries.clear();
+            self.protagonist = Some(protagonist);
+        }
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new()
+    }
+}
+
+// Same as analyze_alphabeta, but with a transposition table that the caller keeps, so that the positions
+// stored in one analysis are reused in the next
+pub fn analyze_alphabeta_with_table(grid: Grid, protagonist: Player, depth: u8, table: &mut TranspositionTable) -> (usize, f64) {
+    table.prepare(protagonist);
+    search_root(grid, protagonist, depth, DEFAULT_EPSILON, &[], HeuristicParams::default(), &mut table.entries,
+                table.capacity, &mut SearchStats::default())
+}
+
+// Alpha-beta search that keeps its transposition table from one analysis to the next, see TranspositionTable
+pub struct Engine {
+    table: TranspositionTable,
+    epsilon: f64,                           // Move values closer than this are considered tied
+    params: HeuristicParams,
+    progress: Option<ProgressCallback>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine { table: TranspositionTable::new(), epsilon: DEFAULT_EPSILON, params: HeuristicParams::default(), progress: None }
+    }
+
+    // Sets the maximal number of positions in the transposition table. A full table is cleared when a new position
+    // has to be stored, which keeps the memory use bounded at the cost of searching some positions again.
+    pub fn table_capacity(mut self, capacity: usize) -> Self {
+        self.table = TranspositionTable::with_capacity(capacity);
+        self
+    }
+
+    // Sets how close the values of two moves must be for them to be tied. Ties are broken by the immediate value.
+    pub fn epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    // Turns on the optional heuristic terms. The stored positions are forgotten since their values change.
+    pub fn heuristic_params(mut self, params: HeuristicParams) -> Self {
+        self.params = params;
+        self.clear();
+        self
+    }
+
+    // Gives the progress of every analysis to the callback, like analyze_with_progress
+    pub fn progress(mut self, progress: impl Fn(SearchProgress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(ProgressCallback(Arc::new(progress)));
+        self
+    }
+
+    // Same as analyze_alphabeta, reusing the positions stored in the previous analyses
+    pub fn analyze(&mut self, grid: Grid, protagonist: Player, depth: u8) -> (usize, f64) {
+        self.table.prepare(protagonist);
+        let mut stats = SearchStats { progress: self.progress.clone(), ..Default::default() };
+        search_root(grid, protagonist, depth, self.epsilon, &[], self.params, &mut self.table.entries,
+                    self.table.capacity, &mut stats)
+    }
+
+    // Forgets all stored positions
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
 
-    let mut transp_table: HashMap<u64, (f64, i8)> = HashMap::new();
+    // Entries of the transposition table as (canonical position hash, value, value type, depth), for inspecting the search.
+    // The value type is 0 for exact values, -1 for alpha values and 1 for beta values.
+    // The depth is the number of moves that were left to search from the position.
+    pub fn entries(&self) -> impl Iterator<Item = (u64, f64, i8, u8)> + '_ {
+        self.table.entries.iter()
+            .map(|(&state_id, &(value, value_type, depth))| (state_id, value, value_type, depth))
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
+    }
+}
+
+// Values closer than this are considered equal when picking a move
+pub const DEFAULT_EPSILON: f64 = 1e-9;
+
+// Checks if two values are equal up to epsilon
+fn values_tied(a: f64, b: f64, epsilon: f64) -> bool {
+    a == b || (a - b).abs() <= epsilon
+}
+
+// Picks the best move by searching every child of the root with the given transposition table
+fn search_root(grid: Grid, protagonist: Player, depth: u8, epsilon: f64, forbidden: &[usize], params: HeuristicParams,
+               transp_table: &mut HashMap<u64, (f64, i8, u8)>, capacity: usize, stats: &mut SearchStats) -> (usize, f64) {
+    let mut best_col = grid.width();  // This is an illegal move but should always be overridden.
     
+    // A depth of 0 would underflow, so it is searched like depth 1: every move is evaluated right after it is played
+    let depth = depth.max(1);
+
+    // Only the forced move needs to be searched if there is one
+    let forced_col = forced_root_move(&grid, protagonist).filter(|col| !forbidden.contains(col));
+
+    let root_node = Node::with_params(grid, params);
     
     let mut best_value = f64::NEG_INFINITY;
     let mut best_immediate_value = f64::NEG_INFINITY;
-    for ([row, col], mut child) in root_node.create_children() {
-        child.update_threat_map(row, col);
-
+    let children: Vec<_> = root_node.create_children().into_iter()
+        .filter(|([_, col], _)| forced_col.is_none_or(|forced| forced == *col) && !forbidden.contains(col))
+        .collect();
+    stats.root_moves = children.len();
+    for (index, ([row, col], mut child)) in children.into_iter().enumerate() {
+        stats.root_move = index;
+        stats.report_progress();
         let child_value = child.get_value_alpha_beta(depth-1, protagonist, row, col, 
                                                           f64::NEG_INFINITY, f64::INFINITY,
-                                                          &mut transp_table);
+                                                          transp_table, capacity, stats);
         let child_immediate_value = child.heuristic(protagonist);
 
-        if child_value >= best_value {
-            if (child_value == best_value) && (child_immediate_value <= best_immediate_value) {
-                continue
-            }
+        // Values within epsilon of each other are tied, so that float noise in the heuristic doesn't decide
+        let improves = match values_tied(child_value, best_value, epsilon) {
+            true => child_immediate_value > best_immediate_value + epsilon,
+            false => child_value > best_value
+        };
+        if improves {
             best_immediate_value = child_immediate_value;
             best_value = child_value;
             best_col = col;
@@ -865,4 +2405,443 @@ pub fn analyze_alphabeta(grid: Grid, protagonist: u8, depth: u8) -> (usize, f64)
     
     
     return (best_col, best_value)
-}
\ No newline at end of file
+}
+
+// Finds a root move that is decided without searching: a column where the protagonist wins right away,
+// or otherwise the column that blocks the enemy's only immediate threat.
+// Gives None if neither exists.
+fn forced_root_move(grid: &Grid, protagonist: Player) -> Option<usize> {
+    if let Some(&col) = grid.immediate_threats(protagonist).first() {
+        return Some(col)
+    }
+    let enemy_threats = grid.immediate_threats(protagonist.other());
+    if enemy_threats.len() == 1 {
+        return Some(enemy_threats[0])
+    }
+    return None
+}
+// Gives the value of every legal move for the protagonist, sorted from best to worst.
+// Moves with equal value are ordered by their immediate value, the same tie-break that analyze_alphabeta uses.
+// 
+// Every move is searched with the full (alpha, beta) window so that all values are exact.
+// On a symmetric grid a move has the same value as its mirrored move, so only one of them is searched.
+pub fn analyze_alphabeta_all(grid: Grid, protagonist: Player, depth: u8) -> Vec<(usize, f64)> {
+    let depth = depth.max(1);       // Same as in search_root
+    let symmetric = grid.is_symmetric();
+    let root_node = Node::new(grid);
+
+    let mut transp_table: HashMap<u64, (f64, i8, u8)> = HashMap::new();
+
+    let mut ranked: Vec<(usize, f64, f64)> = Vec::new();
+    for ([row, col], mut child) in root_node.create_children() {
+        if symmetric {
+            let mirror_col = root_node.grid.w - 1 - col;
+            if let Some(&(_, value, immediate_value)) = ranked.iter().find(|(c, _, _)| *c == mirror_col) {
+                ranked.push((col, value, immediate_value));
+                continue
+            }
+        }
+        let child_value = child.get_value_alpha_beta(depth-1, protagonist, row, col, 
+                                                          f64::NEG_INFINITY, f64::INFINITY,
+                                                          &mut transp_table, DEFAULT_TABLE_CAPACITY, &mut SearchStats::default());
+        let child_immediate_value = child.heuristic(protagonist);
+        
+        ranked.push((col, child_value, child_immediate_value));
+    }
+    ranked.sort_by(|(_, a, a_immediate), (_, b, b_immediate)| {
+        b.total_cmp(a).then(b_immediate.total_cmp(a_immediate))
+    });
+
+    ranked.into_iter().map(|(col, value, _)| (col, value)).collect()
+}
+
+// Same as analyze_alphabeta, but moves with tied values are decided by the given comparison of columns.
+// The column that compares as Greater is preferred. Columns that compare as Equal keep the order of
+// analyze_alphabeta_all, so a comparison that always gives Equal picks the move with the best immediate value.
+// Returns grid.width() (an illegal move) if the grid is full.
+pub fn analyze_with_tie_break(grid: Grid, protagonist: Player, depth: u8,
+                              mut tie_break: impl FnMut(usize, usize) -> Ordering) -> (usize, f64) {
+    let width = grid.width();
+    let ranked = analyze_alphabeta_all(grid, protagonist, depth);
+    let Some(&(_, best_value)) = ranked.first() else {
+        return (width, f64::NEG_INFINITY)
+    };
+
+    let best_col = ranked.iter()
+        .filter(|&&(_, value)| values_tied(value, best_value, DEFAULT_EPSILON))
+        .map(|&(col, _)| col)
+        .reduce(|best, col| match tie_break(col, best) {
+            Ordering::Greater => col,
+            _ => best
+        })
+        .unwrap_or(width);
+    (best_col, best_value)
+}
+
+// Gives the value for the protagonist of playing in the given column, searched to the given depth.
+// The depth includes the move itself, like in analyze_alphabeta.
+// Returns None if the move is illegal.
+pub fn value_of_move(grid: &Grid, protagonist: Player, col: usize, depth: u8) -> Option<f64> {
+    if !grid.legal_moves().contains(&col) {
+        return None
+    }
+
+    let mut child = Node::new(grid.clone());
+    let row = child.grid.play(col);
+
+    let mut transp_table: HashMap<u64, (f64, i8, u8)> = HashMap::new();
+    let value = child.get_value_alpha_beta(depth.saturating_sub(1), protagonist, row, col, 
+                                           f64::NEG_INFINITY, f64::INFINITY,
+                                           &mut transp_table, DEFAULT_TABLE_CAPACITY, &mut SearchStats::default());
+    Some(value)
+}
+
+// Gives every distinct position that can be reached in exactly depth moves from the empty grid,
+// together with one sequence of columns that leads to it. Positions that are mirror images of each other
+// count as the same position. Games that are won before the last move are not continued.
+pub fn enumerate_openings(l: usize, w: usize, h: usize, depth: u8) -> Vec<(Vec<usize>, Grid)> {
+    let mut openings = vec![(Vec::new(), Grid::new(l, w, h))];
+    for _ in 0..depth {
+        let mut seen = HashSet::new();
+        let mut next_openings = Vec::new();
+
+        for (moves, grid) in openings {
+            for col in grid.legal_moves() {
+                let mut child = grid.clone();
+                let row = child.play(col);
+                if !seen.insert(child.canonical_hash()) {
+                    continue
+                }
+
+                let mut child_moves = moves.clone();
+                child_moves.push(col);
+                if child.win_fast(row, col) == 0 || child.turn == depth {
+                    next_openings.push((child_moves, child));
+                }
+            }
+        }
+        openings = next_openings;
+    }
+    openings
+}
+
+// Checks if playing in the column gives the player the best outcome that can be seen within the depth:
+// a forced win if there is one, otherwise any move that doesn't lead to a forced loss.
+// Illegal moves are never solutions.
+pub fn is_puzzle_solution(grid: &Grid, player: Player, col: usize, depth: u8) -> bool {
+    let Some(value) = value_of_move(grid, player, col, depth) else {
+        return false
+    };
+    let best_value = grid.legal_moves().into_iter()
+        .filter_map(|c| value_of_move(grid, player, c, depth))
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    match best_value >= WIN_VALUE {
+        true => value >= WIN_VALUE,
+        false => value > -WIN_VALUE
+    }
+}
+
+// Quality of a played move compared to the best move in the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveQuality {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,        // Throws away a forced win or walks into a forced loss
+}
+
+// Largest loss in heuristic value (compared to the best move) for each classification
+const GOOD_MAX_LOSS: f64 = 2.;
+const INACCURACY_MAX_LOSS: f64 = 6.;
+
+// Classifies a move the player made by how much value it loses compared to the best move, searched to the given depth.
+// Values within DEFAULT_EPSILON of the best value count as the best move, like in analyze_alphabeta.
+// Gives None if the played move is illegal, which includes every move on a full grid.
+pub fn classify_move(grid: &Grid, player: Player, played: usize, depth: u8) -> Option<MoveQuality> {
+    let ranked = analyze_alphabeta_all(grid.clone(), player, depth);
+
+    let &(_, best_value) = ranked.first()?;
+    let &(_, played_value) = ranked.iter().find(|(col, _)| *col == played)?;
+
+    // Changing the outcome of the game is always a blunder, however small the loss in value
+    let lost_win = best_value >= WIN_VALUE && played_value < WIN_VALUE;
+    let walked_into_loss = best_value > -WIN_VALUE && played_value <= -WIN_VALUE;
+    if lost_win || walked_into_loss {
+        return Some(MoveQuality::Blunder)
+    }
+
+    let loss = best_value - played_value;
+    if loss <= 0. || values_tied(best_value, played_value, DEFAULT_EPSILON) {
+        return Some(MoveQuality::Best)
+    }
+    else if loss <= GOOD_MAX_LOSS {
+        return Some(MoveQuality::Good)
+    }
+    else if loss <= INACCURACY_MAX_LOSS {
+        return Some(MoveQuality::Inaccuracy)
+    }
+    return Some(MoveQuality::Mistake)
+}
+
+// Replays a game on an empty l, w, h grid and finds the first move that classify_move calls a Mistake or a Blunder.
+// Returns its ply (0 for the first move) and classification, or None if every move was fine.
+// The replay stops at an illegal move or when the game is won.
+pub fn first_blunder(l: usize, w: usize, h: usize, moves: &[usize], depth: u8) -> Option<(usize, MoveQuality)> {
+    let mut grid = Grid::new(l, w, h);
+    for (ply, &col) in moves.iter().enumerate() {
+        if !grid.can_play(col) {
+            return None
+        }
+        let player = grid.player_to_move();
+        let quality = classify_move(&grid, player, col, depth);
+        if let Some(quality @ (MoveQuality::Mistake | MoveQuality::Blunder)) = quality {
+            return Some((ply, quality))
+        }
+
+        let row = grid.play(col);
+        if grid.win_fast(row, col) != 0 {
+            return None
+        }
+    }
+    return None
+}
+
+// Replays the moves from the starting grid and evaluates the position before every move, for plotting
+// how the evaluation changed over a game. All values are from player 1's perspective, so positive values
+// favor player 1 whichever player was to move.
+// Stops early if a move is illegal or the game is already over.
+pub fn evaluation_history(start: &Grid, moves: &[usize], depth: u8) -> Vec<f64> {
+    let mut grid = start.clone();
+    let mut history = Vec::with_capacity(moves.len());
+
+    for &col in moves {
+        if grid.game_state() != GameState::InProgress || !grid.legal_moves().contains(&col) {
+            break
+        }
+        let to_move = grid.player_to_move();
+        let (_, value) = analyze_alphabeta(grid.clone(), to_move, depth);
+        history.push(match to_move {
+            Player::One => value,
+            Player::Two => -value
+        });
+        grid.play(col);
+    }
+    history
+}
+
+// Gives every column whose value is the same as the value of the given column (up to DEFAULT_EPSILON),
+// including the column itself, in increasing order. On symmetric grids this shows that the mirrored move is equally good.
+// Gives an empty vector if the column is not a legal move.
+pub fn equivalent_moves(grid: &Grid, protagonist: Player, depth: u8, col: usize) -> Vec<usize> {
+    let ranked = analyze_alphabeta_all(grid.clone(), protagonist, depth);
+    let Some(&(_, value)) = ranked.iter().find(|(c, _)| *c == col) else {
+        return Vec::new()
+    };
+
+    let mut equivalent: Vec<usize> = ranked.into_iter()
+        .filter(|&(_, v)| values_tied(v, value, DEFAULT_EPSILON))
+        .map(|(c, _)| c)
+        .collect();
+    equivalent.sort();
+    equivalent
+}
+
+// Checks if the player to move loses by force within the depth whatever they play, e.g. to offer resignation.
+// Gives false if the grid is full.
+pub fn is_lost(grid: &Grid, depth: u8) -> bool {
+    let ranked = analyze_alphabeta_all(grid.clone(), grid.player_to_move(), depth);
+    !ranked.is_empty() && ranked.iter().all(|&(_, value)| value <= -WIN_VALUE)
+}
+
+// The terms that make up the heuristic value of a position, see eval_breakdown
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalBreakdown {
+    pub rows: Vec<f64>,     // Contribution of the threats on each row, starting with the bottom row
+    pub center: f64,        // The center_bonus term of HeuristicParams
+    pub optional: f64,      // The other terms of HeuristicParams
+    pub total: f64,         // Same as static_eval
+}
+
+// Heuristic value of the position for the protagonist, without searching any moves.
+pub fn static_eval(grid: &Grid, protagonist: Player) -> f64 {
+    eval_breakdown(grid, protagonist).total
+}
+
+// Splits the heuristic value of the position for the protagonist into its terms, for debugging the heuristic.
+pub fn eval_breakdown(grid: &Grid, protagonist: Player) -> EvalBreakdown {
+    eval_breakdown_with_params(grid, protagonist, HeuristicParams::default())
+}
+
+// Same as eval_breakdown, with the optional terms of the heuristic
+pub fn eval_breakdown_with_params(grid: &Grid, protagonist: Player, params: HeuristicParams) -> EvalBreakdown {
+    let node = Node::with_params(grid.clone(), params);
+    let threat_map = ThreatMap::from_grid(grid);
+
+    let rows: Vec<f64> = (0..grid.h).map(|i| node.row_score(&threat_map, i, protagonist)).collect();
+    let center = params.center_score(grid, protagonist);
+    let optional = params.threat_score(grid, protagonist);
+    let total = node.heuristic(protagonist);
+
+    EvalBreakdown { rows, center, optional, total }
+}
+
+// Picks a move at random for a more human-like opponent. Every move is chosen with a probability proportional to
+// exp(value/temperature), so better moves are more likely. Higher temperatures give weaker moves more often.
+// A temperature of 0 always gives the move of analyze_alphabeta. The same seed always gives the same move.
+// Returns grid.width() (an illegal move) if the grid is full.
+pub fn analyze_softmax(grid: Grid, protagonist: Player, depth: u8, temperature: f64, seed: u64) -> usize {
+    if temperature <= 0. {
+        return analyze_alphabeta(grid, protagonist, depth).0
+    }
+
+    let ranked = analyze_alphabeta_all(grid.clone(), protagonist, depth);
+    let Some(&(best_col, best_value)) = ranked.first() else {
+        return grid.width()
+    };
+
+    // Subtracting the best value keeps the weights finite when a value is as large as WIN_VALUE
+    let weights: Vec<f64> = ranked.iter()
+        .map(|&(_, value)| ((value - best_value)/temperature).exp())
+        .collect();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut pick = rng.gen::<f64>()*weights.iter().sum::<f64>();
+    for (&(col, _), weight) in ranked.iter().zip(weights) {
+        if pick < weight {
+            return col
+        }
+        pick -= weight;
+    }
+    best_col        // Only reached through rounding errors
+}
+
+// Makes sure the computer plays a legal move, even if the column was chosen from an analysis of an older grid.
+// Gives the column if it can still be played. Otherwise gives the best legal move according to analyze_alphabeta_all.
+// Returns grid.width() (an illegal move) if the grid is full.
+pub fn legal_fallback(grid: &Grid, protagonist: Player, depth: u8, col: usize) -> usize {
+    if grid.can_play(col) {
+        return col
+    }
+    analyze_alphabeta_all(grid.clone(), protagonist, depth).first()
+        .map(|&(col, _)| col)
+        .unwrap_or(grid.w)
+}
+
+// Finds the smallest search depth at which analyze_alphabeta sees a forced win for the player, who should be to move.
+// A win in n moves of the player is found at depth 2n-1. Gives None if no win is found within max_depth.
+pub fn depth_to_detect_win(grid: &Grid, player: Player, max_depth: u8) -> Option<u8> {
+    (1..=max_depth).find(|&depth| analyze_alphabeta(grid.clone(), player, depth).1 >= WIN_VALUE)
+}
+
+// Checks if the player, who has to be the player to move, can win against every defense within depth moves
+// (counting the moves of both players). Unlike the alpha-beta search no heuristic is involved, so a true answer
+// is always right, but a win that needs more moves than depth gives false. Works for every grid height.
+pub fn is_forced_win(grid: &Grid, player: Player, depth: u8) -> bool {
+    if grid.player_to_move() != player {
+        return false
+    }
+    return forced_win_search(grid, player.number(), depth)
+}
+
+// Search for is_forced_win, with the player to move
+fn forced_win_search(grid: &Grid, player: u8, depth: u8) -> bool {
+    if depth == 0 {
+        return false
+    }
+    let moves = grid.legal_moves();
+    for &col in &moves {
+        let mut child = grid.clone();
+        let row = child.play(col);
+        if child.win_fast(row, col) == player {
+            return true
+        }
+    }
+    if depth < 3 {
+        return false
+    }
+
+    // A move wins if the player still has a forced win after every reply of the enemy
+    moves.iter().any(|&col| {
+        let mut child = grid.clone();
+        child.play(col);
+        let replies = child.legal_moves();
+
+        !replies.is_empty() && replies.iter().all(|&reply| {
+            let mut grandchild = child.clone();
+            let row = grandchild.play(reply);
+            grandchild.win_fast(row, reply) == 0 && forced_win_search(&grandchild, player, depth-2)
+        })
+    })
+}
+
+// Squares the second player can take with the claimeven strategy from Victor Allis's solution of connect four,
+// to refute the first player's lines through them.
+// 
+// Claimeven works in columns with an even number of discs. The empty squares of such a column are paired from the
+// bottom up, and whenever the first player plays the lower square of a pair, the second player answers on top of it.
+// That gives the second player every even square (counting the bottom row as row 1) in the column.
+// 
+// Gives the even squares as (row, col), with row 0 at the bottom, that the second player gets this way and that
+// lie on a line the first player could still complete. Meant for positions where the first player is to move.
+pub fn claimeven_squares(grid: &Grid) -> Vec<(usize, usize)> {
+    let open_lines: Vec<Vec<(usize, usize)>> = grid.lines().into_iter()
+        .filter(|line| line.iter().all(|&(i, j)| grid.read(i, j) != 2))
+        .collect();
+
+    let mut squares = Vec::new();
+    for j in 0..grid.w {
+        let lowest_empty = grid.landing_row(j);
+        if lowest_empty%2 == 1 {
+            continue                        // Odd number of discs: the squares can't be paired
+        }
+        // The upper square of each pair is on row 1, 3, 5, ... with row 0 at the bottom
+        for i in (lowest_empty+1..grid.h).step_by(2) {
+            if open_lines.iter().any(|line| line.contains(&(i, j))) {
+                squares.push((i, j))
+            }
+        }
+    }
+    squares
+}
+
+// Gives the column and the resulting grid of every legal move, from the center outwards like in the search
+pub fn children(grid: &Grid) -> Vec<(usize, Grid)> {
+    grid.next_grids().into_iter()
+        .map(|([_, col], child)| (col, child))
+        .collect()
+}
+
+// Picks a move for an easy computer opponent that acts as a sparring partner: it avoids losing right away,
+// but leaves wins on the table and sets up as few threats of its own as possible, so the human gets chances to win.
+// 
+// Moves after which the enemy can win immediately are avoided, and so are the player's own winning moves,
+// unless no other moves are left. Among the remaining moves the one that leaves the player with the fewest threats
+// is picked, preferring central columns at equal numbers.
+// Returns grid.width() (an illegal move) if the grid is full.
+pub fn sparring_move(grid: &Grid, player: Player) -> usize {
+    let mut safe = Vec::new();          // (col, own threats after the move, wins right away)
+    let mut fallback = Vec::new();
+    for ([row, col], child) in grid.next_grids() {
+        let wins = child.win_fast(row, col) == player.number();
+        let loses = !wins && !child.immediate_threats(player.other()).is_empty();
+        fallback.push(col);
+        if !loses {
+            safe.push((col, child.threat_cells(player.number()).len(), wins));
+        }
+    }
+
+    let non_winning: Vec<&(usize, usize, bool)> = safe.iter().filter(|(_, _, wins)| !wins).collect();
+    let candidates = match non_winning.is_empty() {
+        true => safe.iter().collect(),
+        false => non_winning
+    };
+
+    // min_by_key keeps the first of equal moves, and the moves are in center first order
+    candidates.into_iter()
+        .min_by_key(|(_, threats, _)| *threats)
+        .map(|&(col, _, _)| col)
+        .or(fallback.first().copied())
+        .unwrap_or(grid.w)
+}
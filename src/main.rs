@@ -1,11 +1,33 @@
-use std::{fmt, io, time::Instant};
+#![allow(clippy::needless_return, clippy::too_many_arguments)]
 
-use terminal_con4::{Grid, analyze_alphabeta, analyze_bfs_mt};
+use std::{env, fmt, fs, io::{self, Write}, time::Instant};
+
+use terminal_con4::{Engine, GameState, Grid, Player, analyze_alphabeta, endgame_depth, is_puzzle_solution, legal_fallback, moves_to_end};
+use terminal_con4::record::{GameRecord, ParseRecordError, today};
+use terminal_con4::render::cell_width;
 
 
 // Change this to true if there are display issues
 const NEVER_CLEAR: bool = false;
 
+// Menu settings are loaded from this file at startup and saved to it on exit
+const SETTINGS_FILE: &str = "con4_settings.txt";
+
+// Entering [v] during a game saves it to this file, and [o] in the menu loads it
+const SAVE_FILE: &str = "con4_save.txt";
+
+// Positions where exactly one move wins for the player to move, in the Grid::to_code() format
+const PUZZLES: [&str; 5] = [
+    "4,7,6:/x/o/o/o//xx",
+    "4,7,6:x//o/o///x",
+    "4,7,6://oox/o//xx/xo",
+    "4,7,6:o/x//x/o/ooxo/oxx",
+    "4,7,6:x//xx/o/xo/oooxo/x",
+];
+
+// Search depth used to check puzzle answers
+const PUZZLE_DEPTH: u8 = 7;
+
 fn clear_lines() {
     // Clears the terminal. Should work for windows
 
@@ -16,32 +38,99 @@ fn clear_lines() {
     print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
 }
 
+// Number of lines printed together with the grid during a game, besides the grid rows
+const GAME_TEXT_LINES: usize = 4;
+
+// Checks if a grid with w columns and h rows can be shown in a terminal of the given size.
+// The grid is one cell per column plus the turn marker wide and has a header row.
+fn fits_terminal(w: usize, h: usize, cols: u16, rows: u16) -> bool {
+    cell_width(w)*w + 6 <= cols as usize && 1 + h + GAME_TEXT_LINES <= rows as usize
+}
+
+// Gives the terminal size as (columns, rows) if it can be determined.
+// The COLUMNS and LINES environment variables take precedence, otherwise the terminal is asked,
+// which works on Windows as well as on Unix. Gives None if stdout is not a terminal.
+fn terminal_size() -> Option<(u16, u16)> {
+    let from_env = |var: &str| env::var(var).ok().and_then(|value| value.trim().parse::<u16>().ok());
+    if let (Some(cols), Some(rows)) = (from_env("COLUMNS"), from_env("LINES")) {
+        return Some((cols, rows))
+    }
+
+    let (terminal_size::Width(cols), terminal_size::Height(rows)) = terminal_size::terminal_size()?;
+    Some((cols, rows))
+}
+
 // Requests a single character from terminal input
 fn input_char() -> Result<char, std::char::ParseCharError> {
     let mut input: String = String::new();
     io::stdin().read_line(&mut input).expect("Failed to read line");
-    let len = input.len();
-    let str = &input[len-3..len-2];
     
-    str.parse::<char>()
+    // Trimming removes the line ending, which is "\n" or "\r\n" depending on the platform
+    input.trim().parse::<char>()
+}
+
+// Requests a line from terminal input, without the line ending
+fn input_line() -> String {
+    let mut input: String = String::new();
+    io::stdin().read_line(&mut input).expect("Failed to read line");
+    
+    input.trim_end_matches(['\r', '\n']).to_string()
 }
 
 // Requests an unsigned integer from terminal input
 fn input_usize() -> Result<usize, std::num::ParseIntError> {
     let mut input: String = String::new();
     io::stdin().read_line(&mut input).expect("Failed to read line");
-    let len = input.len();
-    let str = &input[..len-2];
     
-    str.parse::<usize>()
+    input.trim().parse::<usize>()
 }
 
 
+// A game that was paused with [v], with everything besides the menu settings that is needed to continue it
+struct PausedGame {
+    record: GameRecord,             // The players and the moves so far
+    cpu_player: Option<Player>,     // None in a game between two players
+    depth: u8,                      // Search depth, which adaptive depth may have raised during the game
+}
+
+impl PausedGame {
+    // The game as "key=value" lines, which are written after the menu settings in the save file.
+    // The record is put on one line, since reading it back only needs whitespace between the properties and the moves.
+    fn to_save(&self) -> String {
+        let cpu_player = self.cpu_player.map_or(0, Player::number);
+        format!("cpu_player={cpu_player}\ndepth={}\nrecord={}\n", self.depth, self.record.to_record().replace('\n', " "))
+    }
+
+    // Reads the lines written by to_save(). Lines with other keys, like the menu settings, are skipped.
+    fn from_save(save: &str) -> Result<Self, ParseRecordError> {
+        let mut cpu_player = None;
+        let mut depth = Menu::new().start_depth;
+        let mut record = Err(ParseRecordError::MissingProperty("record"));
+        for line in save.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue
+            };
+            match key.trim() {
+                "cpu_player" => cpu_player = value.trim().parse().ok().and_then(Player::from_number),
+                "depth" => depth = value.trim().parse().unwrap_or(depth),
+                "record" => record = GameRecord::from_record(value),
+                _ => ()
+            }
+        }
+        Ok(PausedGame { record: record?, cpu_player, depth })
+    }
+}
+
 // A game where both sides are controlled by terminal input.
-// Used for games with two human players
-fn custom_game(keep_history: bool, l: usize, w: usize, h: usize) {
+// Used for games with two human players.
+// Continues the paused game if there is one. Returns the game if it is paused again by entering [v].
+fn custom_game(keep_history: bool, l: usize, w: usize, h: usize, swap_rule: bool, resume: Option<PausedGame>) -> Option<PausedGame> {
 
-    let mut grid = Grid::new(l, w, h);
+    let mut record = match resume {
+        Some(paused) => paused.record,
+        None => GameRecord::new(l, w, h, "Player 1", "Player 2", &today())
+    };
+    let mut grid = record.grid();
 
     while grid.turn() < (w*h) as u8 {
         if !keep_history {
@@ -49,45 +138,147 @@ fn custom_game(keep_history: bool, l: usize, w: usize, h: usize) {
         }
         println!("{grid}");
 
-        match input_usize() {
-            Ok(col) if col < grid.width() => {
-                grid.play(col);
+        let input = input_line();
+        if input.trim() == "v" {
+            return Some(PausedGame { record, cpu_player: None, depth: 0 })
+        }
+        match input.trim().parse::<usize>() {
+            Ok(col) => {
+                if grid.try_play(col).is_err() {
+                    continue                        // Full or nonexistent column, ask again
+                }
+                record.record_move(col);
             },
             _ => continue
         }
 
         match grid.win_highlight() {
-            win if win == 1 => {
+            Some(Player::One) => {
                 if !keep_history {clear_lines()}
                 println!("\no won after {} turns!", grid.turn());
                 println!("{}", grid);
-                return
+                record.set_result(GameState::Won(Player::One));
+                print_record(&record);
+                return None
             },
-            win if win == 2 => {
+            Some(Player::Two) => {
                 if !keep_history {clear_lines()}
                 println!("\nx won after {} turns!", grid.turn());
                 println!("{}", grid);
-                return
+                record.set_result(GameState::Won(Player::Two));
+                print_record(&record);
+                return None
             }
-            _ => ()
+            None => ()
         };
+
+        if swap_rule && grid.turn() == 1 {
+            // Pie rule: the second player may take over the first disc instead of replying
+            println!("{grid}");
+            println!("Swap sides? [y/n]");
+            if let Ok('y') = input_char() {
+                println!("Sides swapped: the second player now plays o and the first player plays x");
+                std::mem::swap(&mut record.player_1, &mut record.player_2);
+            }
+        }
     }
     println!("draw");
     println!("{}", grid);
-    
+    record.set_result(GameState::Draw);
+    print_record(&record);
+    None
+}
+
+// Shows the record of a finished game so that it can be copied
+fn print_record(record: &GameRecord) {
+    println!("\nGame record:\n{record}");
+}
+
+// Engine for the computer's moves in a game. Its transposition table is kept for the whole game.
+// The progress is written over itself on one line, so that the screen doesn't look frozen during long searches.
+fn cpu_engine() -> Engine {
+    Engine::new().progress(|progress| {
+        print!("\rSearching move {} of {}, {} positions visited", progress.root_move+1, progress.root_moves, progress.nodes);
+        let _ = io::stdout().flush();
+    })
+}
+
+// Lets the computer pick a move for the player to move, and prints the move with the given name for the player.
+// Gives the column and the number of seconds the analysis took.
+fn cpu_move(grid: &Grid, depth: u8, name: &str, engine: &mut Engine) -> (usize, f32) {
+    let player = grid.player_to_move();
+
+    // Search to the end of the game when few empty positions remain
+    let search_depth = endgame_depth(grid, depth);
+
+    println!("Analyzing with depth = {search_depth}");
+    let now = Instant::now();
+    let (col, value) = engine.analyze(grid.clone(), player, search_depth);
+    println!();
+    let col = legal_fallback(grid, player, search_depth, col);
+    let calc_time = now.elapsed().as_secs_f32();
+    println!("{name} played in column {} (value: {:.4}) after {} seconds", col, value, calc_time);         
+    if let Some(moves) = moves_to_end(value, search_depth) {
+        if value < 0. {
+            println!("{name} sees a forced loss. Its best defense is column {col}, the game ends in {moves} moves");
+        }
+        else {
+            println!("{name} sees a forced win in {moves} moves");
+        }
+    }
+    (col, calc_time)
+}
+
+// Shows the move the computer would play for the player to move, without playing it
+fn print_hint(grid: &Grid, depth: u8) {
+    let player = grid.player_to_move();
+    let search_depth = endgame_depth(grid, depth);
+
+    let (col, value) = analyze_alphabeta(grid.clone(), player, search_depth);
+    let col = legal_fallback(grid, player, search_depth, col);
+    println!("Hint: column {col} (value: {value:.4})");
+}
+
+// Increases the calculation depth if the analysis took less than 1 second
+fn adapted_depth(depth: u8, calc_time: f32) -> u8 {
+    if calc_time < 1. {
+        if calc_time > 0.3  {return depth + 1}
+        else                {return depth + 2}
+    }
+    depth
 }
 
 // A game where one player is controlled by user input and the other by the computer.
-fn adversarial_game(keep_history: bool, l: usize, w: usize, h: usize, cpu_player: u8, mut depth: u8, adaptive_depth: bool) {
-    // cpu_player=1,2 specifies if the computer plays first or second
+// Continues the paused game if there is one. Returns the game if it is paused again by entering [v].
+fn adversarial_game(keep_history: bool, l: usize, w: usize, h: usize, mut cpu_player: Player, mut depth: u8, adaptive_depth: bool,
+                    swap_rule: bool, resume: Option<PausedGame>) -> Option<PausedGame> {
+    // cpu_player specifies if the computer plays first or second
 
     // depth specifies how many layers of subsequent moves the computer will take into account
 
     // If adaptive_depth is true, then the depth will increase as the tree of possible moves
     // shinks over the course of the game
 
-    let mut grid = Grid::new(l, w, h);
-
+    // If swap_rule is true, then the second player may take over the first disc after the first move
+
+    let mut last_col;
+    let mut engine = cpu_engine();
+
+    let mut record = match resume {
+        Some(paused) => {
+            cpu_player = paused.cpu_player.unwrap_or(cpu_player);
+            depth = paused.depth;
+            paused.record
+        },
+        None => {
+            let (name_1, name_2) = match cpu_player {
+                Player::One => ("Computer", "You"),
+                Player::Two => ("You", "Computer")
+            };
+            GameRecord::new(l, w, h, name_1, name_2, &today())
+        }
+    };
+    let mut grid = record.grid();
 
     while grid.turn() < (w*h) as u8 {
         if !keep_history {
@@ -96,59 +287,194 @@ fn adversarial_game(keep_history: bool, l: usize, w: usize, h: usize, cpu_player
         println!("{grid}");
         
         if grid.player_to_move() == cpu_player {
-            println!("Analyzing with depth = {depth}");
-            let calc_time: f32;
-            let now = Instant::now();
-            if h%2==0 {
-                let (col, value) = analyze_alphabeta(grid.clone(), cpu_player, depth);
-                calc_time = now.elapsed().as_secs_f32();
-                println!("The computer played in column {} (value: {:.4}) after {} seconds", col, value, calc_time);         
-                grid.play(col);
-            }
-            else {
-                let col = analyze_bfs_mt(grid.clone(), cpu_player, depth);
-                calc_time = now.elapsed().as_secs_f32();
-                println!("The computer played in column {} after {} seconds", col, calc_time);         
-                grid.play(col);
-            }
-            
+            let (col, calc_time) = cpu_move(&grid, depth, "The computer", &mut engine);
+            grid.play(col);
+            record.record_move(col);
+            last_col = col;
+
             if adaptive_depth {
-                // Increase the calculation depth if it the analysis took less than 1 second.
-                if calc_time < 1. {
-                    if calc_time > 0.3  {depth += 1}
-                    else                {depth += 2}
-                }
+                depth = adapted_depth(depth, calc_time);
             }
             
         } else {
-            match input_usize() {
-                Ok(col) if col < grid.width() => {
-                    grid.play(col);
+            // Hints are asked for with [?] and don't use up the move
+            let mut input = input_line();
+            while input.trim() == "?" {
+                print_hint(&grid, depth);
+                input = input_line();
+            }
+            if input.trim() == "v" {
+                return Some(PausedGame { record, cpu_player: Some(cpu_player), depth })
+            }
+            match input.trim().parse::<usize>() {
+                Ok(col) => {
+                    if grid.try_play(col).is_err() {
+                        continue                    // Full or nonexistent column, ask again
+                    }
+                    record.record_move(col);
+                    last_col = col;
                 },
                 _ => continue
             }
         }
 
         match grid.win_highlight() {
-            win if win == cpu_player => {
+            Some(winner) if winner == cpu_player => {
                 if !keep_history {clear_lines()}
 
                 println!("\nThe computer won after {} turns!", grid.turn());
                 println!("{}", grid);
-                return
+                record.set_result(GameState::Won(cpu_player));
+                print_record(&record);
+                return None
             },
-            win if win == (3 - cpu_player) => {                   // 3-1 = 2, 3-2 = 1
+            Some(_) => {
                 if !keep_history {clear_lines()}
                 println!("\nYou won after {} turns!", grid.turn());
                 println!("{}", grid);
+                record.set_result(GameState::Won(cpu_player.other()));
+                print_record(&record);
+                return None
+            }
+            None => ()
+        };
+
+        if swap_rule && grid.turn() == 1 {
+            // Pie rule: the sides are relabeled, the grid is left as it is
+            if cpu_player == Player::Two {
+                if cpu_wants_swap(&grid, h, depth, last_col) {
+                    cpu_player = Player::One;
+                    println!("The computer swapped sides and now plays o");
+                    std::mem::swap(&mut record.player_1, &mut record.player_2);
+                }
+            } else {
+                println!("{grid}");
+                println!("Swap sides? [y/n]");
+                if let Ok('y') = input_char() {
+                    cpu_player = Player::Two;
+                    std::mem::swap(&mut record.player_1, &mut record.player_2);
+                }
+            }
+        }
+    }
+    println!("DRAW");
+    println!("{}", grid);
+    record.set_result(GameState::Draw);
+    print_record(&record);
+    None
+}
+
+
+// A game where both sides are played by the computer, with its own search depth for each side.
+// Used for comparing depths, so adaptive depth raises the depth of each side separately.
+fn cpu_game(keep_history: bool, l: usize, w: usize, h: usize, depth_1: u8, depth_2: u8, adaptive_depth: bool, swap_rule: bool) {
+    let mut grid = Grid::new(l, w, h);
+    let mut depths = [depth_1, depth_2];
+    let mut engines = [cpu_engine(), cpu_engine()];
+    let mut record = GameRecord::new(l, w, h, &format!("Computer (depth {depth_1})"), &format!("Computer (depth {depth_2})"), &today());
+
+    while grid.turn() < (w*h) as u8 {
+        if !keep_history {
+            clear_lines()
+        }
+        println!("{grid}");
+
+        let player = grid.player_to_move();
+        let side = player.number() as usize - 1;
+        let name = match player {
+            Player::One => "o",
+            Player::Two => "x"
+        };
+        let (col, calc_time) = cpu_move(&grid, depths[side], name, &mut engines[side]);
+        grid.play(col);
+        record.record_move(col);
+
+        if adaptive_depth {
+            depths[side] = adapted_depth(depths[side], calc_time);
+        }
+
+        match grid.win_highlight() {
+            None => (),
+            Some(winner) => {
+                if !keep_history {clear_lines()}
+                println!("\n{} won after {} turns!", if winner == Player::One {'o'} else {'x'}, grid.turn());
+                println!("{}", grid);
+                record.set_result(GameState::Won(winner));
+                print_record(&record);
                 return
             }
-            _ => ()
         };
+
+        // Pie rule: the computer with x takes over the first disc if it prefers it, so the depths change sides
+        if swap_rule && grid.turn() == 1 && cpu_wants_swap(&grid, h, depths[1], col) {
+            println!("x swapped sides");
+            depths.swap(0, 1);
+            engines.swap(0, 1);
+            std::mem::swap(&mut record.player_1, &mut record.player_2);
+        }
     }
     println!("DRAW");
     println!("{}", grid);
-    
+    record.set_result(GameState::Draw);
+    print_record(&record);
+}
+
+
+// Presents positions where only one move wins and lets the user find it.
+// Entering '?' rules out one more column that doesn't win.
+fn puzzles(keep_history: bool) {
+    for (n, code) in PUZZLES.iter().enumerate() {
+        let grid: Grid = code.parse().expect("The built-in puzzles are valid codes");
+        let player = grid.player_to_move();
+        let mut ruled_out: Vec<usize> = Vec::new();
+
+        loop {
+            if !keep_history {
+                clear_lines()
+            }
+            println!("Puzzle {} of {}: find the winning move for {}", n+1, PUZZLES.len(), match player {Player::One => 'o', Player::Two => 'x'});
+            if !ruled_out.is_empty() {
+                println!("These columns don't win: {ruled_out:?}");
+            }
+            println!("{grid}");
+            println!("Enter a column, or [?] for a hint");
+
+            let input = input_line();
+            if input == "?" {
+                let hint = grid.legal_moves().into_iter()
+                    .find(|&col| !ruled_out.contains(&col) && !is_puzzle_solution(&grid, player, col, PUZZLE_DEPTH));
+                if let Some(col) = hint {
+                    ruled_out.push(col);
+                }
+                continue
+            }
+
+            match input.parse::<usize>() {
+                Ok(col) if is_puzzle_solution(&grid, player, col, PUZZLE_DEPTH) => {
+                    println!("Correct! Column {col} wins. Press enter to continue");
+                    input_line();
+                    break
+                },
+                Ok(col) if col < grid.width() && !ruled_out.contains(&col) => ruled_out.push(col),
+                _ => ()
+            }
+        }
+    }
+    println!("You solved all the puzzles!");
+}
+
+
+// Decides if the computer, as the second player, takes over the first disc under the pie rule.
+// On even heights it swaps if the position is worse for x. On odd heights it swaps if the
+// first disc was played in the central third of the grid.
+fn cpu_wants_swap(grid: &Grid, h: usize, depth: u8, first_col: usize) -> bool {
+    if h.is_multiple_of(2) {
+        let (_, value) = analyze_alphabeta(grid.clone(), Player::Two, depth);
+        value < 0.
+    }
+    else {
+        (2*first_col).abs_diff(grid.width()-1) <= grid.width()/3
+    }
 }
 
 
@@ -160,37 +486,159 @@ struct Menu {
     h: usize,
     game_mode: i8,
     start_depth: u8,
+    start_depth_2: u8,      // Initial depth of the computer playing x when the computer plays both sides
     adaptive_depth: bool,
+    swap_rule: bool,
+    terminal: Option<(u16, u16)>,   // Size of the terminal as (columns, rows), read by run() before every redraw
 }
 impl Menu {
     fn new() -> Self {
         // Default settings
-        Menu { current_page: 0, keep_history: true, l: 4, w: 7, h: 6, game_mode: 1, start_depth: 10, adaptive_depth: true}
+        Menu { current_page: 0, keep_history: true, l: 4, w: 7, h: 6, game_mode: 1, start_depth: 10, start_depth_2: 10,
+               adaptive_depth: true,
+               swap_rule: false,
+               terminal: None}
+    }
+
+    // Settings as "key=value" lines
+    fn to_settings(&self) -> String {
+        format!("keep_history={}\nl={}\nw={}\nh={}\ngame_mode={}\nstart_depth={}\nstart_depth_2={}\nadaptive_depth={}\nswap_rule={}\n",
+                self.keep_history, self.l, self.w, self.h, self.game_mode, self.start_depth, self.start_depth_2, self.adaptive_depth,
+                self.swap_rule)
+    }
+
+    // Reads settings written by to_settings(). Missing or invalid settings keep their default value.
+    fn from_settings(settings: &str) -> Self {
+        let mut menu = Menu::new();
+        for line in settings.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue
+            };
+            let value = value.trim();
+            match key.trim() {
+                "keep_history" => menu.keep_history = value.parse().unwrap_or(menu.keep_history),
+                "l" => menu.l = value.parse().ok().filter(|&l| l > 0).unwrap_or(menu.l),
+                "w" => menu.w = value.parse().ok().filter(|&w| w > 0).unwrap_or(menu.w),
+                "h" => menu.h = value.parse().ok().filter(|&h| h > 0).unwrap_or(menu.h),
+                "game_mode" => menu.game_mode = value.parse().ok()
+                    .filter(|mode| (-1..=2).contains(mode))
+                    .unwrap_or(menu.game_mode),
+                "start_depth" => menu.start_depth = value.parse().ok().filter(|&d| d > 1).unwrap_or(menu.start_depth),
+                "start_depth_2" => menu.start_depth_2 = value.parse().ok().filter(|&d| d > 1).unwrap_or(menu.start_depth_2),
+                "adaptive_depth" => menu.adaptive_depth = value.parse().unwrap_or(menu.adaptive_depth),
+                "swap_rule" => menu.swap_rule = value.parse().unwrap_or(menu.swap_rule),
+                _ => ()
+            }
+        }
+        menu
+    }
+
+    // Loads the settings file, or gives the default settings if there is none
+    fn load(path: &str) -> Self {
+        match fs::read_to_string(path) {
+            Ok(settings) => Menu::from_settings(&settings),
+            Err(_) => Menu::new()
+        }
     }
 
+    fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_settings())
+    }
+
+    // Saves a paused game together with the settings it is played with
+    fn save_game(&self, path: &str, paused: &PausedGame) -> io::Result<()> {
+        fs::write(path, format!("{}{}", self.to_settings(), paused.to_save()))
+    }
 
-    fn begin(&self) {
-        match self.game_mode {
-            0 => custom_game(self.keep_history, self.l, self.w, self.h),
-            -1 => adversarial_game(self.keep_history, self.l, self.w, self.h, 1, self.start_depth, self.adaptive_depth),
-            1 => adversarial_game(self.keep_history, self.l, self.w, self.h, 2, self.start_depth, self.adaptive_depth),
-            _ => panic!("Invalid game mode")
+    // Loads a game saved by save_game() and takes over the settings it was played with
+    fn load_game(&mut self, path: &str) -> Option<PausedGame> {
+        let save = match fs::read_to_string(path) {
+            Ok(save) => save,
+            Err(error) => {
+                println!("Could not open the saved game: {error}");
+                return None
+            }
+        };
+        match PausedGame::from_save(&save) {
+            Ok(paused) => {
+                *self = Menu { current_page: self.current_page, ..Menu::from_settings(&save) };
+                Some(paused)
+            },
+            Err(error) => {
+                println!("Could not read the saved game: {error}");
+                None
+            }
         }
     }
 
+
+    // Starts a game with the current settings, or continues the paused game if there is one.
+    // The game is saved if it is paused again.
+    fn begin(&self, resume: Option<PausedGame>) {
+        let paused = match self.game_mode {
+            0 => custom_game(self.keep_history, self.l, self.w, self.h, self.swap_rule, resume),
+            -1 => adversarial_game(self.keep_history, self.l, self.w, self.h, Player::One, self.start_depth, self.adaptive_depth,
+                                   self.swap_rule, resume),
+            1 => adversarial_game(self.keep_history, self.l, self.w, self.h, Player::Two, self.start_depth, self.adaptive_depth,
+                                  self.swap_rule, resume),
+            2 => {
+                cpu_game(self.keep_history, self.l, self.w, self.h, self.start_depth, self.start_depth_2, self.adaptive_depth,
+                         self.swap_rule);
+                None
+            },
+            _ => {
+                println!("Invalid game mode {}, choose one in the menu", self.game_mode);
+                None
+            }
+        };
+
+        if let Some(paused) = paused {
+            match self.save_game(SAVE_FILE, &paused) {
+                Ok(()) => println!("The game was saved to {SAVE_FILE}, load it with [o] in the menu"),
+                Err(error) => println!("Could not save the game: {error}")
+            }
+        }
+    }
+
+    // Descriptions of settings that make the game behave unexpectedly
+    fn validation_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if self.l > self.w && self.l > self.h {
+            warnings.push(format!("No line of length {} fits in a {} x {} grid, so no one can win.", self.l, self.w, self.h));
+        }
+        if self.w*self.h > u8::MAX as usize {
+            warnings.push(format!("The grid has more than {} positions, which is more turns than the game can count.", u8::MAX));
+        }
+
+        if let Some((cols, rows)) = self.terminal {
+            if !fits_terminal(self.w, self.h, cols, rows) {
+                warnings.push(format!("A {} x {} grid does not fit in this {cols} x {rows} terminal. \
+                                       Consider smaller grid dimensions.", self.w, self.h));
+            }
+        }
+        warnings
+    }
+
     fn input_options_str(&self) -> &str {
 
         match self.current_page {
-            0 => return "\n Play: [p]    Edit setup: [s] \
-                         \n",
+            0 => return "\n Play: [p]    Edit setup: [s]    Puzzles: [z]    Load saved game: [o] \
+                         \n During a game, enter [v] instead of a column to save it and quit, or [?] for a hint\n",
             
             1 if (self.game_mode == 0) => {
                 return "\nToggle keep history: [k]    Set win length: [l]    Set grid dimensions: [w/h]    Exit Setup: [e] \
-                        \nSwitch game mode: [m]\n"
+                        \nSwitch game mode: [m]    Toggle swap rule: [r]\n"
+            },
+            1 if (self.game_mode == 2) => {
+                return "\nToggle keep history: [k]    Set win length: [l]    Set grid dimensions: [w/h]    Exit Setup: [e] \
+                        \nSwitch game mode:    [m]    Set initial depth of o(>1): [d]    Set initial depth of x(>1): [f] \
+                        \nToggle adaptive depth [a]    Toggle swap rule:    [r]"
             },
             1 if (self.game_mode != 0) => {
                 return "\nToggle keep history: [k]    Set win length: [l]    Set grid dimensions: [w/h]    Exit Setup: [e] \
-                        \nSwitch game mode:    [m]    Switch start:   [t]    Set initial depth(>1): [d]    Toggle adaptive depth [a]"
+                        \nSwitch game mode:    [m]    Switch start:   [t]    Set initial depth(>1): [d]    Toggle adaptive depth [a] \
+                        \nToggle swap rule:    [r]"
             },
             _ => return "\nINVALID PAGE"
         }
@@ -199,19 +647,32 @@ impl Menu {
     fn run(&mut self) {
         loop {
             clear_lines();
+            self.terminal = terminal_size();            // The window may have been resized since the last time
             println!("{self}");                         // Show current settings and input options
             
             match self.current_page {
                 // Start screen
                 0 => match input_char() {
                     Ok('p') => {
-                        self.begin();
+                        self.begin(None);
                         break
                     },
+                    Ok('o') => {
+                        if let Some(paused) = self.load_game(SAVE_FILE) {
+                            self.begin(Some(paused));
+                            break
+                        }
+                        println!("Press enter to continue");
+                        input_line();
+                    },
                     Ok('s') => {
                         self.current_page = 1;
                         // self.setup_screen()
                     },
+                    Ok('z') => {
+                        puzzles(self.keep_history);
+                        break
+                    },
                     _ => ()
                 }
 
@@ -223,23 +684,32 @@ impl Menu {
                         _ => continue
                     },
                     Ok('w') => self.w = match input_usize() {
-                        Ok(w) => w,
+                        Ok(w) if (w > 0) => w,
                         _ => continue
                     },
                     Ok('h') => self.h = match input_usize() {
-                        Ok(h) => h,
+                        Ok(h) if (h > 0) => h,
                         _ => continue
                     },
                     Ok('e') => {self.current_page = 0},
 
                     // Cpu settings
-                    Ok('m') => self.game_mode =  (self.game_mode + 1) % 2,              // -1,1 --> 0, 0 --> 1
-                    Ok('t') => self.game_mode =  -self.game_mode,                       // -1 <--> 1, 0 --> 0
+                    Ok('m') => self.game_mode = match self.game_mode {                  // 0 --> 1, -1,1 --> 2, 2 --> 0
+                        0 => 1,
+                        2 => 0,
+                        _ => 2
+                    },
+                    Ok('t') if self.game_mode.abs() == 1 => self.game_mode = -self.game_mode,   // -1 <--> 1
                     Ok('d') => self.start_depth = match input_usize() {
                         Ok(d) if (d > 1) => d as u8,
                         _ => continue
                     },
+                    Ok('f') if self.game_mode == 2 => self.start_depth_2 = match input_usize() {
+                        Ok(d) if (d > 1) => d as u8,
+                        _ => continue
+                    },
                     Ok('a') => self.adaptive_depth = !self.adaptive_depth,
+                    Ok('r') => self.swap_rule = !self.swap_rule,
                     _ => ()
                 }
                 _ => ()
@@ -263,6 +733,7 @@ impl fmt::Display for Menu {
             0  => output = format!("{output}two players"),
             -1 => output = format!("{output}single player, cpu plays first"),
             1  => output = format!("{output}single player, cpu plays second"),
+            2  => output = format!("{output}cpu vs cpu"),
             _  => output = format!("{output}INVALID ({})", self.game_mode)
         }
 
@@ -270,7 +741,10 @@ impl fmt::Display for Menu {
         output = format!("{output}\n     Length to win:      {}      ", self.l);
         
         // Start depth
-        if self.game_mode != 0 {
+        if self.game_mode == 2 {
+            output = format!("{output}       Initial depths:   {} (o), {} (x)", self.start_depth, self.start_depth_2);
+        }
+        else if self.game_mode != 0 {
             output = format!("{output}       Initial depth:    {}", self.start_depth);
         } 
 
@@ -281,8 +755,20 @@ impl fmt::Display for Menu {
         if self.game_mode != 0 {
             output = format!("{output}        Adaptive depth:   {}", match self.adaptive_depth{true => "ON", false => "OFF"});
         } 
+
+        // Pie rule
+        output = format!("{output}\n     Swap rule:          {}", match self.swap_rule{true => "ON ", false => "OFF"});
         
 
+        // Warnings about the settings
+        let warnings = self.validation_warnings();
+        if !warnings.is_empty() {
+            output = format!("{output}\n");
+        }
+        for warning in warnings {
+            output = format!("{output}\n     Warning: {warning}");
+        }
+
         // Input options
         output = format!("{output}\n{}", self.input_options_str());
         write!(f, "{output}")
@@ -292,7 +778,7 @@ impl fmt::Display for Menu {
 
 fn main() {
 
-    let mut menu = Menu::new();
+    let mut menu = Menu::load(SETTINGS_FILE);
     
     'play_again: loop {
         menu.run();
@@ -308,4 +794,8 @@ fn main() {
         }
     }
 
+    if let Err(error) = menu.save(SETTINGS_FILE) {
+        println!("Could not save the settings: {error}");
+    }
+
 }
\ No newline at end of file
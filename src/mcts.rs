@@ -0,0 +1,109 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{GameState, Grid, Player};
+
+
+// Exploration constant in the UCT formula
+const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+// Node in the Monte Carlo search tree. Nodes are stored in a vector and refer to each other by index.
+struct TreeNode {
+    grid: Grid,
+    col: usize,                 // Column that was played to reach this node from its parent
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<usize>,        // Legal moves that don't have a child node yet
+    state: GameState,
+    visits: f64,
+    score: f64,                 // Sum of rollout results for the player who moved into this node. Wins count 1, draws 0.5.
+}
+
+impl TreeNode {
+    fn new(grid: Grid, col: usize, parent: Option<usize>, state: GameState) -> Self {
+        let untried = match state {
+            GameState::InProgress => grid.legal_moves(),
+            _ => Vec::new()
+        };
+        TreeNode { grid, col, parent, children: Vec::new(), untried, state, visits: 0., score: 0. }
+    }
+
+    // Upper confidence bound used to select which child to explore
+    fn uct(&self, parent_visits: f64) -> f64 {
+        self.score/self.visits + EXPLORATION*(parent_visits.ln()/self.visits).sqrt()
+    }
+}
+
+
+// Determines the best move for the protagonist with Monte Carlo tree search.
+// 
+// Every iteration walks down the tree by UCT selection, adds one new node and finishes the game from
+// there with random moves. The column whose node was visited the most is returned.
+// Does not rely on the parity heuristic, so it also suits grids with odd height.
+// 
+// The protagonist should be the player to move. The same seed always gives the same move.
+// Returns grid.width() (an illegal move) if the grid is full.
+pub fn analyze_mcts(grid: Grid, protagonist: Player, iterations: usize, seed: u64) -> usize {
+    debug_assert_eq!(grid.player_to_move(), protagonist);
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let root_state = grid.game_state();
+    let mut tree = vec![TreeNode::new(grid, 0, None, root_state)];
+
+    for _ in 0..iterations {
+        // Selection
+        let mut node = 0;
+        while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+            let parent_visits = tree[node].visits;
+            node = *tree[node].children.iter()
+                .max_by(|&&a, &&b| tree[a].uct(parent_visits).total_cmp(&tree[b].uct(parent_visits)))
+                .expect("The loop only runs for nodes with children");
+        }
+
+        // Expansion
+        if !tree[node].untried.is_empty() {
+            let pick = rng.gen_range(0..tree[node].untried.len());
+            let col = tree[node].untried.swap_remove(pick);
+            let mut child_grid = tree[node].grid.clone();
+            let row = child_grid.play(col);
+            let state = match Player::from_number(child_grid.win_fast(row, col)) {
+                None if child_grid.legal_moves().is_empty() => GameState::Draw,
+                None => GameState::InProgress,
+                Some(winner) => GameState::Won(winner)
+            };
+
+            tree.push(TreeNode::new(child_grid, col, Some(node), state));
+            let child = tree.len() - 1;
+            tree[node].children.push(child);
+            node = child;
+        }
+
+        // Simulation
+        let result = match tree[node].state {
+            GameState::InProgress => tree[node].grid.rollout(|grid| {
+                let legal_moves = grid.legal_moves();
+                legal_moves[rng.gen_range(0..legal_moves.len())]
+            }),
+            state => state
+        };
+
+        // Backpropagation
+        let mut current = Some(node);
+        while let Some(index) = current {
+            let mover = tree[index].grid.player_to_move().other();
+            tree[index].visits += 1.;
+            tree[index].score += match result {
+                GameState::Won(win) if win == mover => 1.,
+                GameState::Draw => 0.5,
+                _ => 0.
+            };
+            current = tree[index].parent;
+        }
+    }
+
+    let best_child = tree[0].children.iter()
+        .max_by(|&&a, &&b| tree[a].visits.total_cmp(&tree[b].visits));
+    match best_child {
+        Some(&child) => tree[child].col,
+        None => tree[0].grid.width()
+    }
+}
@@ -0,0 +1,173 @@
+use std::{fmt, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::{GameState, Grid, Player};
+
+
+// Record of a played game, for archiving and sharing.
+//
+// The text format starts with bracketed properties and is followed by the played columns:
+//      [l 4][w 7][h 6][o Alice][x Bob][date 2026-10-16][result o]
+//      3 3 2 4 1
+// The result is o or x for a win, draw for a draw and * for a game that is not finished.
+// Only l, w and h are required when reading a record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRecord {
+    pub l: usize,
+    pub w: usize,
+    pub h: usize,
+    pub player_1: String,       // Plays the o discs
+    pub player_2: String,       // Plays the x discs
+    pub date: String,
+    pub moves: Vec<usize>,
+    pub result: GameState,
+}
+
+impl GameRecord {
+    pub fn new(l: usize, w: usize, h: usize, player_1: &str, player_2: &str, date: &str) -> Self {
+        GameRecord {
+            l, w, h,
+            player_1: player_1.to_string(),
+            player_2: player_2.to_string(),
+            date: date.to_string(),
+            moves: Vec::new(),
+            result: GameState::InProgress,
+        }
+    }
+
+    // Adds a move to the end of the game. The game loops call this for every disc that is played.
+    pub fn record_move(&mut self, col: usize) {
+        self.moves.push(col);
+    }
+
+    pub fn set_result(&mut self, result: GameState) {
+        self.result = result;
+    }
+
+    // Gives the grid after all recorded moves
+    pub fn grid(&self) -> Grid {
+        let mut grid = Grid::new(self.l, self.w, self.h);
+        for &col in &self.moves {
+            grid.play(col);
+        }
+        grid
+    }
+
+    pub fn to_record(&self) -> String {
+        // Property values end at the first ']', so it can't be part of a name
+        let clean = |value: &str| value.replace(']', ")");
+
+        let result = match self.result {
+            GameState::Won(Player::One) => "o",
+            GameState::Won(Player::Two) => "x",
+            GameState::Draw => "draw",
+            GameState::InProgress => "*"
+        };
+        let moves: Vec<String> = self.moves.iter().map(|col| col.to_string()).collect();
+
+        format!("[l {}][w {}][h {}][o {}][x {}][date {}][result {}]\n{}",
+            self.l, self.w, self.h, clean(&self.player_1), clean(&self.player_2), clean(&self.date), result,
+            moves.join(" "))
+    }
+
+    // Reads a record in the format written by to_record. Every move has to be legal.
+    pub fn from_record(record: &str) -> Result<Self, ParseRecordError> {
+        let mut rest = record.trim_start();
+        let (mut l, mut w, mut h) = (None, None, None);
+        let mut game = GameRecord::new(0, 0, 0, "", "", "");
+
+        while let Some(property) = rest.strip_prefix('[') {
+            let (property, after) = property.split_once(']')
+                .ok_or(ParseRecordError::InvalidProperty(property.to_string()))?;
+            rest = after.trim_start();
+
+            let (key, value) = property.split_once(' ').unwrap_or((property, ""));
+            let dimension = || match value.trim().parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Some(n)),
+                _ => Err(ParseRecordError::InvalidProperty(property.to_string()))
+            };
+            match key {
+                "l" => l = dimension()?,
+                "w" => w = dimension()?,
+                "h" => h = dimension()?,
+                "o" => game.player_1 = value.to_string(),
+                "x" => game.player_2 = value.to_string(),
+                "date" => game.date = value.to_string(),
+                "result" => game.result = match value.trim() {
+                    "o" => GameState::Won(Player::One),
+                    "x" => GameState::Won(Player::Two),
+                    "draw" => GameState::Draw,
+                    "*" => GameState::InProgress,
+                    _ => return Err(ParseRecordError::InvalidProperty(property.to_string()))
+                },
+                _ => ()         // Unknown properties are skipped
+            }
+        }
+
+        game.l = l.ok_or(ParseRecordError::MissingProperty("l"))?;
+        game.w = w.ok_or(ParseRecordError::MissingProperty("w"))?;
+        game.h = h.ok_or(ParseRecordError::MissingProperty("h"))?;
+
+        let mut grid = Grid::new(game.l, game.w, game.h);
+        for (ply, token) in rest.split_whitespace().enumerate() {
+            let col = token.parse::<usize>()
+                .map_err(|_| ParseRecordError::InvalidMove(token.to_string()))?;
+            if !grid.legal_moves().contains(&col) {
+                return Err(ParseRecordError::IllegalMove { ply, col })
+            }
+            grid.play(col);
+            game.record_move(col);
+        }
+
+        Ok(game)
+    }
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_record())
+    }
+}
+
+
+// Reasons why a game record could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseRecordError {
+    MissingProperty(&'static str),          // One of the required properties l, w or h
+    InvalidProperty(String),
+    InvalidMove(String),                    // The move is not a column number
+    IllegalMove { ply: usize, col: usize }, // The column is outside the grid or full
+}
+
+impl fmt::Display for ParseRecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseRecordError::MissingProperty(key) => write!(f, "the record has no \"{key}\" property"),
+            ParseRecordError::InvalidProperty(property) => write!(f, "invalid property \"[{property}]\""),
+            ParseRecordError::InvalidMove(token) => write!(f, "invalid move \"{token}\", expected a column number"),
+            ParseRecordError::IllegalMove { ply, col } => write!(f, "move {} in column {col} is illegal", ply+1),
+        }
+    }
+}
+
+impl std::error::Error for ParseRecordError {}
+
+
+// Today's date in UTC as YYYY-MM-DD, for the date of a record
+pub fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs()/86400)
+        .unwrap_or(0) as i64;
+
+    // Converts days since 1970-01-01 to a civil date, counting eras of 400 years that start on March 1st
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era*146097;
+    let year_of_era = (day_of_era - day_of_era/1460 + day_of_era/36524 - day_of_era/146096)/365;
+    let day_of_year = day_of_era - (365*year_of_era + year_of_era/4 - year_of_era/100);
+    let month_index = (5*day_of_year + 2)/153;
+    let day = day_of_year - (153*month_index + 2)/5 + 1;
+    let month = if month_index < 10 {month_index + 3} else {month_index - 9};
+    let year = year_of_era + era*400 + if month <= 2 {1} else {0};
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
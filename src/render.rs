@@ -0,0 +1,270 @@
+use std::{fmt, io::{self, IsTerminal}};
+
+use crate::{Grid, Player};
+
+
+// RGBA colors used by Grid::to_rgba
+pub const BOARD_COLOR: [u8; 4] = [30, 70, 200, 255];
+pub const EMPTY_COLOR: [u8; 4] = [240, 240, 240, 255];
+pub const PLAYER_1_COLOR: [u8; 4] = [220, 40, 40, 255];
+pub const PLAYER_2_COLOR: [u8; 4] = [240, 210, 30, 255];
+pub const PLAYER_1_HIGHLIGHT_COLOR: [u8; 4] = [255, 140, 140, 255];
+pub const PLAYER_2_HIGHLIGHT_COLOR: [u8; 4] = [255, 245, 150, 255];
+
+// ANSI escape codes used by the colored text representation.
+// Highlighted discs are bold and in the bright variant of the player color.
+const ANSI_PLAYER_1: &str = "\x1b[31m";
+const ANSI_PLAYER_2: &str = "\x1b[33m";
+const ANSI_PLAYER_1_HIGHLIGHT: &str = "\x1b[1;91m";
+const ANSI_PLAYER_2_HIGHLIGHT: &str = "\x1b[1;93m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+// Characters used for the discs and the empty positions in the text representation of a grid
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisplayStyle {
+    pub player_1: char,
+    pub player_2: char,
+    pub empty: char,
+}
+
+impl Default for DisplayStyle {
+    fn default() -> Self {
+        DisplayStyle { player_1: 'o', player_2: 'x', empty: ' ' }
+    }
+}
+
+// Text representation of a grid with adjustable layout.
+// The default layout is the one used by the Display implementation of Grid.
+pub struct GridRenderer<'a> {
+    grid: &'a Grid,
+    verbose: bool,      // Adds row labels and a legend
+    style: DisplayStyle,
+    color: bool,        // Colors the discs with ANSI escape codes
+}
+
+impl<'a> GridRenderer<'a> {
+    pub fn new(grid: &'a Grid) -> Self {
+        GridRenderer { grid, verbose: false, style: DisplayStyle::default(), color: false }
+    }
+
+    // Labels the rows (0 is the bottom row) and adds a legend with the discs and the turn count
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    // Draws the discs and empty positions with the characters of the style
+    pub fn style(mut self, style: DisplayStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    // Colors the discs of the two players differently with ANSI escape codes, and highlighted discs in bold.
+    // Only the discs are colored, so the layout is the same as without colors.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+// Number of characters per cell: the brackets and room for the widest column index.
+// The room inside the brackets is odd so that the discs stay centered.
+pub fn cell_width(w: usize) -> usize {
+    let widest_index = format!("{}", w.saturating_sub(1)).len();
+    (widest_index | 1) + 2
+}
+
+impl fmt::Display for GridRenderer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let grid = self.grid;
+        let DisplayStyle { player_1, player_2, empty } = self.style;
+
+        // Row labels are right aligned in a margin wide enough for the highest row index
+        let margin = match self.verbose {
+            true => format!("{}", grid.h-1).len() + 1,
+            false => 0
+        };
+
+        // Cells are wide enough for the largest column index, so that the header stays aligned on wide grids
+        let cell = cell_width(grid.w);
+        let inner = cell - 2;
+
+        // The text is padded before the escape codes are added, since they take up no room in a terminal
+        let paint = |text: String, code: &str| match self.color {
+            true => format!("{code}{text}{ANSI_RESET}"),
+            false => text
+        };
+
+        let mut output = String::with_capacity(cell*grid.w*grid.h);
+        output = format!("{output}{:margin$}", "");
+        for j in 0..grid.w {
+            output = format!("{output}{j:^cell$}")
+        }
+        match grid.player_to_move() {
+            Player::One => output = format!("{output}  ({}) ", paint(player_1.to_string(), ANSI_PLAYER_1)),
+            Player::Two => output = format!("{output}  ({}) ", paint(player_2.to_string(), ANSI_PLAYER_2)),
+        }
+        for i in 0..grid.h {
+            output = format!("{output}\n");
+            if self.verbose {
+                output = format!("{output}{:>width$} ", grid.h-i-1, width = margin-1);
+            }
+            for j in 0..grid.w {
+                let highlighted = grid.is_highlighted(grid.h-i-1, j);
+                output = match (grid.read(grid.h-i-1, j), highlighted) {
+                    (0, _) => format!("{output}[{empty:^inner$}]"),
+                    (1, false) => format!("{output}[{}]", paint(format!("{player_1:^inner$}"), ANSI_PLAYER_1)),
+                    (2, false) => format!("{output}[{}]", paint(format!("{player_2:^inner$}"), ANSI_PLAYER_2)),
+                    // Used when highlighting player 1 win
+                    (1, true) => format!("{output} {} ", paint(format!("{player_1:^inner$}"), ANSI_PLAYER_1_HIGHLIGHT)),
+                    // Used when highlighting player 2 win
+                    (2, true) => format!("{output} {} ", paint(format!("{player_2:^inner$}"), ANSI_PLAYER_2_HIGHLIGHT)),
+                    _ => format!("{output}{:^cell$}", "err")
+                }
+            }
+        }
+        if self.verbose {
+            output = format!("{output}\n{player_1}: player 1    {player_2}: player 2    turn: {}", grid.turn);
+        }
+        write!(f, "{output}")
+    }
+}
+
+
+impl Grid {
+    // Gives a renderer for the grid whose layout can be adjusted
+    pub fn renderer(&self) -> GridRenderer<'_> {
+        GridRenderer::new(self)
+    }
+
+    // Gives the text representation of the grid with other characters for the discs, e.g. '●' and '○'.
+    // Highlighted discs are drawn with the same characters without brackets, like in the default representation.
+    pub fn display_with(&self, style: DisplayStyle) -> GridRenderer<'_> {
+        GridRenderer::new(self).style(style)
+    }
+
+    // Gives the text representation of the grid with the discs colored by ANSI escape codes,
+    // and the discs of a highlighted winning line in bold.
+    // Falls back to the plain text representation when stdout is not a terminal, e.g. when the output is piped.
+    // renderer().color(true) always colors.
+    pub fn to_ansi_string(&self) -> String {
+        self.renderer().color(io::stdout().is_terminal()).to_string()
+    }
+
+    // Draws the grid as an RGBA image with cell_px x cell_px pixels per cell, for example for thumbnails.
+    // Gives the width and height of the image in pixels together with the pixel data, row by row from the top.
+    pub fn to_rgba(&self, cell_px: usize) -> (usize, usize, Vec<u8>) {
+        let width = self.w*cell_px;
+        let height = self.h*cell_px;
+        let mut buffer = Vec::with_capacity(width*height*4);
+
+        let radius = 0.4*cell_px as f64;
+        let center = 0.5*cell_px as f64;
+        for y in 0..height {
+            let row = self.h - 1 - y/cell_px;
+            for x in 0..width {
+                let col = x/cell_px;
+
+                // Distance from the pixel center to the center of the cell
+                let dx = (x%cell_px) as f64 + 0.5 - center;
+                let dy = (y%cell_px) as f64 + 0.5 - center;
+
+                let color = if dx*dx + dy*dy > radius*radius {
+                    BOARD_COLOR
+                }
+                else {
+                    match (self.read(row, col), self.is_highlighted(row, col)) {
+                        (1, false) => PLAYER_1_COLOR,
+                        (2, false) => PLAYER_2_COLOR,
+                        (1, true) => PLAYER_1_HIGHLIGHT_COLOR,
+                        (2, true) => PLAYER_2_HIGHLIGHT_COLOR,
+                        _ => EMPTY_COLOR
+                    }
+                };
+                buffer.extend_from_slice(&color);
+            }
+        }
+
+        return (width, height, buffer)
+    }
+
+    // Draws the grid as a standalone SVG image with cell_px x cell_px pixels per cell, for example for documentation.
+    // Discs are circles in the player colors, and highlighted discs get the highlight color and a white outline.
+    // Empty positions are drawn as fully rounded squares, so that every <circle> in the image is a disc.
+    pub fn to_svg(&self, cell_px: usize) -> String {
+        let hex = |color: [u8; 4]| format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]);
+
+        let width = self.w*cell_px;
+        let height = self.h*cell_px;
+        let radius = 0.4*cell_px as f64;
+
+        let mut output = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+                                  viewBox=\"0 0 {width} {height}\">\n");
+        output = format!("{output}<rect width=\"{width}\" height=\"{height}\" fill=\"{}\"/>\n", hex(BOARD_COLOR));
+        for i in 0..self.h {
+            let row = self.h - 1 - i;
+            let cy = (i as f64 + 0.5)*cell_px as f64;
+            for col in 0..self.w {
+                let cx = (col as f64 + 0.5)*cell_px as f64;
+                output = match (self.read(row, col), self.is_highlighted(row, col)) {
+                    (0, _) => format!("{output}<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{radius}\" fill=\"{}\"/>\n",
+                                      cx - radius, cy - radius, 2.*radius, 2.*radius, hex(EMPTY_COLOR)),
+                    (player, highlighted) => {
+                        let color = match (player, highlighted) {
+                            (1, false) => PLAYER_1_COLOR,
+                            (1, true) => PLAYER_1_HIGHLIGHT_COLOR,
+                            (_, false) => PLAYER_2_COLOR,
+                            (_, true) => PLAYER_2_HIGHLIGHT_COLOR,
+                        };
+                        let outline = match highlighted {
+                            true => format!(" stroke=\"#ffffff\" stroke-width=\"{}\"", 0.08*cell_px as f64),
+                            false => String::new()
+                        };
+                        format!("{output}<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{radius}\" fill=\"{}\"{outline}/>\n", hex(color))
+                    }
+                }
+            }
+        }
+        format!("{output}</svg>\n")
+    }
+}
+
+
+// Frames of a disc falling into the column, for animating a move. The disc is drawn in every empty
+// position above the one it lands on, starting from the top row, followed by one frame of the grid after the move.
+// Gives no frames if the move is illegal.
+pub fn render_drop_frames(grid: &Grid, col: usize) -> Vec<String> {
+    if !grid.legal_moves().contains(&col) {
+        return Vec::new()
+    }
+    let player = grid.player_to_move();
+    let row = grid.landing_row(col);
+
+    let mut frames = Vec::with_capacity(grid.h - row);
+    for i in (row+1..grid.h).rev() {
+        let mut frame = grid.clone();
+        frame.set(i, col, player.number());
+        frames.push(frame.to_string());
+    }
+
+    let mut settled = grid.clone();
+    settled.play(col);
+    frames.push(settled.to_string());
+    frames
+}
+
+// Frames for replaying a whole game from the starting grid: the drop animation of every move, one after the other.
+// Stops at the first illegal move.
+pub fn replay_frames(start: &Grid, moves: &[usize]) -> Vec<String> {
+    let mut grid = start.clone();
+    let mut frames = Vec::new();
+    for &col in moves {
+        let drop = render_drop_frames(&grid, col);
+        if drop.is_empty() {
+            break
+        }
+        frames.extend(drop);
+        grid.play(col);
+    }
+    frames
+}
@@ -0,0 +1,48 @@
+use crate::{Grid, analyze_alphabeta};
+
+
+// Test position with the moves that are known to be best for the player to move
+pub struct SuitePosition {
+    pub name: &'static str,
+    pub code: &'static str,             // In the Grid::to_code() format
+    pub best_moves: &'static [usize],
+}
+
+// Positions for tracking the strength of the engine and catching regressions.
+// Every position has exactly one move that wins or saves the game. The mate in n positions are won in n own moves.
+pub const SUITE: [SuitePosition; 8] = [
+    SuitePosition { name: "mate in 1", code: "4,7,6:ooo/xxx/////", best_moves: &[0] },
+    SuitePosition { name: "forced block", code: "4,7,6:xxx/oo/o////", best_moves: &[0] },
+    SuitePosition { name: "mate in 2", code: "4,7,6:xx//o/o///", best_moves: &[4] },
+    SuitePosition { name: "open three", code: "4,7,6:/x/o/o/o//xx", best_moves: &[5] },
+    SuitePosition { name: "double threat", code: "4,7,6:x//o/o///x", best_moves: &[4] },
+    SuitePosition { name: "diagonal setup", code: "4,7,6://oox/o//xx/xo", best_moves: &[1] },
+    SuitePosition { name: "stacked threats", code: "4,7,6:o/x//x/o/ooxo/oxx", best_moves: &[6] },
+    SuitePosition { name: "crowded center", code: "4,7,6:x//xx/o/xo/oooxo/x", best_moves: &[3] },
+];
+
+// Outcome of running the engine on the suite
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuiteResult {
+    pub solved: usize,
+    pub total: usize,
+    pub failed: Vec<&'static str>,      // Names of the positions where the engine played another move
+}
+
+// Runs analyze_alphabeta with the given depth on every position of the suite and counts
+// how often it plays one of the best moves.
+pub fn run_suite(depth: u8) -> SuiteResult {
+    let mut result = SuiteResult { solved: 0, total: SUITE.len(), failed: Vec::new() };
+
+    for position in SUITE.iter() {
+        let grid: Grid = position.code.parse().expect("The suite positions are valid codes");
+        let (col, _) = analyze_alphabeta(grid.clone(), grid.player_to_move(), depth);
+
+        if position.best_moves.contains(&col) {
+            result.solved += 1
+        } else {
+            result.failed.push(position.name)
+        }
+    }
+    result
+}
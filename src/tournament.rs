@@ -0,0 +1,99 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{DEFAULT_EPSILON, Grid, Player, analyze_alphabeta_all, values_tied};
+
+
+// Settings of a computer player
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EngineConfig {
+    pub depth: u8,
+}
+
+// Outcome of a series of games between engine a and engine b
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TournamentResult {
+    pub wins_a: usize,
+    pub wins_b: usize,
+    pub draws: usize,
+}
+
+impl TournamentResult {
+    pub fn games(&self) -> usize {
+        self.wins_a + self.wins_b + self.draws
+    }
+}
+
+
+// Picks a move for the player to move.
+// All moves tied with the best value are collected and one of them is picked with the rng.
+// Returns grid.width() (an illegal move) if the grid is full.
+fn choose_move(grid: &Grid, config: EngineConfig, rng: &mut StdRng) -> usize {
+    let protagonist = grid.player_to_move();
+
+    let ranked = analyze_alphabeta_all(grid.clone(), protagonist, config.depth);
+    let Some(&(_, best_value)) = ranked.first() else {
+        return grid.width()
+    };
+    let tied: Vec<usize> = ranked.iter()
+        .filter(|(_, value)| values_tied(*value, best_value, DEFAULT_EPSILON))
+        .map(|(col, _)| *col)
+        .collect();
+
+    tied[rng.gen_range(0..tied.len())]
+}
+
+
+// Plays a game from the given grid to the end. engine_1 plays the o discs and engine_2 the x discs.
+// Returns which player won or None if the game was a draw.
+pub fn self_play(mut grid: Grid, engine_1: EngineConfig, engine_2: EngineConfig, rng: &mut StdRng) -> Option<Player> {
+    while !grid.legal_moves().is_empty() {
+        let config = match grid.player_to_move() {
+            Player::One => engine_1,
+            Player::Two => engine_2
+        };
+
+        let col = choose_move(&grid, config, rng);
+        let row = grid.play(col);
+
+        if let Some(winner) = Player::from_number(grid.win_fast(row, col)) {
+            return Some(winner)
+        }
+    }
+    None
+}
+
+
+// Plays a number of games between two engines on the standard grid (l=4, w=7, h=6).
+// Engine a plays first in the even numbered games and engine b in the odd numbered games.
+// Ties between equally good moves are broken with a rng seeded by the given seed,
+// so the same seed always gives the same result.
+pub fn tournament(config_a: EngineConfig, config_b: EngineConfig, games: usize, seed: u64) -> TournamentResult {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut result = TournamentResult::default();
+
+    for game in 0..games {
+        let a_starts = game%2 == 0;
+        let winner = match a_starts {
+            true => self_play(Grid::new(4, 7, 6), config_a, config_b, &mut rng),
+            false => self_play(Grid::new(4, 7, 6), config_b, config_a, &mut rng)
+        };
+
+        match (winner, a_starts) {
+            (None, _) => result.draws += 1,
+            (Some(Player::One), true) | (Some(Player::Two), false) => result.wins_a += 1,
+            _ => result.wins_b += 1
+        }
+    }
+    result
+}
+
+
+// Lets two engines pick a move on the same grid, for quick comparisons without a full tournament.
+// Returns the move of engine a, the move of engine b and whether they are the same.
+// Both engines break ties with a rng seeded the same way, so identical configs always agree.
+// On a full grid both moves are grid.width() (an illegal move).
+pub fn compare_on(grid: &Grid, a: EngineConfig, b: EngineConfig) -> (usize, usize, bool) {
+    let col_a = choose_move(grid, a, &mut StdRng::seed_from_u64(0));
+    let col_b = choose_move(grid, b, &mut StdRng::seed_from_u64(0));
+    (col_a, col_b, col_a == col_b)
+}